@@ -30,6 +30,10 @@ export function activate(context: ExtensionContext) {
 		synchronize: {
 			// Notify the server about file changes to '.clientrc files contained in the workspace
 			fileEvents: workspace.createFileSystemWatcher('**/.clientrc')
+		},
+		initializationOptions: {
+			dataDirectory: workspace.getConfiguration('languageServer').get('dataDirectory'),
+			pricesDirectory: workspace.getConfiguration('languageServer').get('pricesDirectory')
 		}
 	};
 
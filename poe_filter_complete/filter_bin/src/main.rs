@@ -1,15 +1,24 @@
 use filter_lib::*;
 use log::info;
-use lsp_server::{Connection, Message, Notification, Request, RequestId, Response};
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, RequestId, Response};
 use lsp_types::notification::*;
 use lsp_types::request::*;
 use lsp_types::*;
 use std::error::Error;
 use std::fs;
+use std::path::{Path, PathBuf};
 
 fn main() -> Result<(), Box<dyn Error + Sync + Send>> {
     flexi_logger::Logger::with_str("info").start().unwrap();
     // info!("starting POE Filter LSP server");
+    match std::env::args().nth(1).as_deref() {
+        Some("retier") => return retier(),
+        Some("strictness") => return strictness(),
+        Some("style") => return style(),
+        Some("extract-style") => return extract_style(),
+        Some("build") => return build(),
+        _ => {}
+    }
 
     let (connection, io_threads) = Connection::stdio();
 
@@ -26,43 +35,33 @@ fn main() -> Result<(), Box<dyn Error + Sync + Send>> {
         hover_provider: Some(true),
         completion_provider: Some(CompletionOptions {
             resolve_provider: None,
-            trigger_characters: Some(vec!["\"".to_string()]),
+            trigger_characters: Some(vec!["\"".to_string(), " ".to_string()]),
+            work_done_progress_options: WorkDoneProgressOptions {
+                work_done_progress: None,
+            },
+        }),
+        signature_help_provider: Some(SignatureHelpOptions {
+            trigger_characters: Some(vec![" ".to_string()]),
+            retrigger_characters: None,
             work_done_progress_options: WorkDoneProgressOptions {
                 work_done_progress: None,
             },
         }),
-        // signature_help_provider: Some(SignatureHelpOptions {
-        //     trigger_characters: Some(vec!["(".to_string(), ",".to_string()]),
-        //     retrigger_characters: None,
-        //     work_done_progress_options: WorkDoneProgressOptions {
-        //         work_done_progress: None,
-        //     },
-        // }),
         // declaration_provider: None,
-        // definition_provider: Some(true),
+        definition_provider: Some(true),
         // type_definition_provider: Some(TypeDefinitionProviderCapability::Simple(true)),
         // implementation_provider: Some(ImplementationProviderCapability::Simple(true)),
-        // references_provider: Some(true),
-        // document_highlight_provider: Some(true),
+        references_provider: Some(true),
+        document_highlight_provider: Some(true),
         // document_symbol_provider: Some(true),
-        // workspace_symbol_provider: Some(true),
-        // code_action_provider: Some(CodeActionProviderCapability::Options(CodeActionOptions {
-        // Advertise support for all built-in CodeActionKinds
-        // code_action_kinds: Some(vec![
-        // lsp_types::code_action_kind::EMPTY.to_string(),
-        // lsp_types::code_action_kind::QUICKFIX.to_string(),
-        // lsp_types::code_action_kind::REFACTOR.to_string(),
-        // lsp_types::code_action_kind::REFACTOR_EXTRACT.to_string(),
-        // lsp_types::code_action_kind::REFACTOR_INLINE.to_string(),
-        // lsp_types::code_action_kind::REFACTOR_REWRITE.to_string(),
-        // lsp_types::code_action_kind::SOURCE.to_string(),
-        // lsp_types::code_action_kind::SOURCE_ORGANIZE_IMPORTS.to_string(),
-        //     ]),
-        //     work_done_progress_options: Default::default(),
-        // })),
-        // code_lens_provider: Some(CodeLensOptions {
-        //     resolve_provider: Some(true),
-        // }),
+        workspace_symbol_provider: Some(true),
+        code_action_provider: Some(CodeActionProviderCapability::Options(CodeActionOptions {
+            code_action_kinds: Some(vec![code_action_kind::REFACTOR_EXTRACT.to_string()]),
+            work_done_progress_options: Default::default(),
+        })),
+        code_lens_provider: Some(CodeLensOptions {
+            resolve_provider: Some(false),
+        }),
         // document_formatting_provider: Some(true),
         // document_range_formatting_provider: None,
         // document_on_type_formatting_provider: Some(DocumentOnTypeFormattingOptions {
@@ -72,15 +71,23 @@ fn main() -> Result<(), Box<dyn Error + Sync + Send>> {
         // selection_range_provider: Some(SelectionRangeProviderCapability::Simple(true)),
         // semantic_highlighting: None,
         // folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
-        // rename_provider: Some(RenameProviderCapability::Options(RenameOptions {
-        //     prepare_provider: Some(true),
-        //     work_done_progress_options: WorkDoneProgressOptions {
-        //         work_done_progress: None,
-        //     },
-        // })),
-        // document_link_provider: None,
+        rename_provider: Some(RenameProviderCapability::Options(RenameOptions {
+            prepare_provider: Some(true),
+            work_done_progress_options: WorkDoneProgressOptions {
+                work_done_progress: None,
+            },
+        })),
+        document_link_provider: Some(DocumentLinkOptions {
+            resolve_provider: None,
+            work_done_progress_options: WorkDoneProgressOptions {
+                work_done_progress: None,
+            },
+        }),
         // color_provider: None,
-        // execute_command_provider: None,
+        execute_command_provider: Some(ExecuteCommandOptions {
+            commands: vec![code_lens::SHOW_BASES.to_string()],
+            work_done_progress_options: Default::default(),
+        }),
         // workspace: None,
         // call_hierarchy_provider: Some(CallHierarchyServerCapability::Simple(true)),
         // semantic_tokens_provider: Some(
@@ -99,23 +106,106 @@ fn main() -> Result<(), Box<dyn Error + Sync + Send>> {
         experimental: Default::default(),
         ..ServerCapabilities::default()
     };
-    let server_capabilities = serde_json::to_value(&server_settings).unwrap();
+    let mut server_capabilities = serde_json::to_value(&server_settings).unwrap();
+    // lsp-types 0.74 has no field for inlay hints, so the capability is added by hand.
+    server_capabilities["inlayHintProvider"] = serde_json::Value::Bool(true);
     let initialization_params = connection.initialize(server_capabilities)?;
-    main_loop(&connection, initialization_params)?;
+    main_loop(
+        &connection,
+        initialization_params,
+        path_arg("--data-dir"),
+        path_arg("--prices-dir"),
+    )?;
     io_threads.join()?;
 
-    // info!("shutting down server");
+    info!("shutting down server");
     Ok(())
 }
 
 fn main_loop(
     connection: &Connection,
-    _params: serde_json::Value,
+    params: serde_json::Value,
+    data_dir: Option<PathBuf>,
+    prices_dir: Option<PathBuf>,
 ) -> Result<(), Box<dyn Error + Sync + Send>> {
     // info!("starting main loop");
 
     let mut parsed_filter = mode_parsing::Filter::default();
-    let poe_data = data_parsing::PoeData::new();
+    let mut document = String::new();
+    let mut document_path = PathBuf::new();
+    let mut imported = vec![];
+    // Code lenses for the current version of the document, once asked for.
+    let mut lenses = None;
+    let params = serde_json::from_value::<InitializeParams>(params).ok();
+    let options = params
+        .as_ref()
+        .and_then(|p| p.initialization_options.clone());
+    let snippets = params
+        .as_ref()
+        .and_then(|p| {
+            p.capabilities
+                .text_document
+                .as_ref()?
+                .completion
+                .as_ref()?
+                .completion_item
+                .as_ref()?
+                .snippet_support
+        })
+        .unwrap_or(false);
+    let can_watch = params
+        .as_ref()
+        .and_then(|p| {
+            p.capabilities
+                .workspace
+                .as_ref()?
+                .did_change_watched_files
+                .as_ref()?
+                .dynamic_registration
+        })
+        .unwrap_or(false);
+    let root = params.and_then(|p| p.root_uri?.to_file_path().ok());
+    let mut files = root.as_deref().map(workspace::scan).unwrap_or_default();
+    let tracked = |path: &Path| root.as_deref().is_some_and(|r| workspace::tracked(r, path));
+    let mut index = symbols::SymbolIndex::new(&files);
+    if can_watch {
+        watch_filters(connection);
+    }
+    let option_path = |name: &str| {
+        options
+            .as_ref()
+            .and_then(|o| o.get(name)?.as_str().map(PathBuf::from))
+    };
+    let data_dir = option_path("dataDirectory").or(data_dir);
+    let prices_dir = option_path("pricesDirectory").or(prices_dir);
+    let (mut poe_data, error) = data_parsing::PoeData::load(data_dir.as_deref());
+    match (error, data_dir) {
+        (Some(error), _) => show_message(
+            connection,
+            MessageType::Error,
+            format!("Using bundled game data: {}", error),
+        ),
+        (None, Some(dir)) if !poe_data.bundled().is_empty() => show_message(
+            connection,
+            MessageType::Warning,
+            format!(
+                "{} has no {}, using the bundled ones",
+                dir.display(),
+                poe_data.bundled().join(" or ")
+            ),
+        ),
+        _ => {}
+    }
+    if let Some(dir) = prices_dir {
+        match economy::Prices::from_dir(&dir) {
+            Ok(prices) => poe_data.prices = prices,
+            Err(error) => show_message(
+                connection,
+                MessageType::Error,
+                format!("Could not load price snapshots: {}", error),
+            ),
+        }
+    }
 
     for msg in &connection.receiver {
         match msg {
@@ -124,27 +214,151 @@ fn main_loop(
                     return Ok(());
                 }
                 let mut request = ReqMessage { req };
-                if let Some(resp) = handle_hover(&mut request, &parsed_filter) {
+                if let Some(resp) = handle_hover(
+                    &mut request,
+                    &parsed_filter,
+                    &document,
+                    &imported,
+                    &poe_data,
+                ) {
                     // info!("request: {:?}", resp);
                     handle_request(connection, resp);
                 }
                 if let Some(resp) =
-                    handle_completion(&mut request, &parsed_filter, poe_data.clone())
+                    handle_completion(&mut request, &parsed_filter, &document, &poe_data, snippets)
+                {
+                    handle_request(connection, resp);
+                }
+                if let Some(resp) = handle_signature_help(&mut request, &document) {
+                    handle_request(connection, resp);
+                }
+                if let Some(resp) =
+                    handle_definition(&mut request, &parsed_filter, &document, &imported)
+                {
+                    handle_request(connection, resp);
+                }
+                let workspace = Workspace {
+                    files: &files,
+                    imported: &imported,
+                    document_path: &document_path,
+                };
+                if let Some(resp) =
+                    handle_references(&mut request, &parsed_filter, &document, &workspace)
+                {
+                    handle_request(connection, resp);
+                }
+                if let Some(resp) = handle_code_lens(
+                    &mut request,
+                    &parsed_filter,
+                    &document,
+                    &poe_data,
+                    &mut lenses,
+                ) {
+                    handle_request(connection, resp);
+                }
+                if let Some(resp) = handle_execute_command(
+                    connection,
+                    &mut request,
+                    &document,
+                    &workspace,
+                    &poe_data,
+                ) {
+                    handle_request(connection, resp);
+                }
+                if let Some(resp) =
+                    handle_inlay_hint(&mut request, &parsed_filter, &document, &poe_data)
                 {
                     handle_request(connection, resp);
                 }
+                if let Some(resp) = handle_workspace_symbol(&mut request, &index) {
+                    handle_request(connection, resp);
+                }
+                if let Some(resp) = handle_prepare_rename(&mut request, &parsed_filter, &document) {
+                    handle_request(connection, resp);
+                }
+                if let Some(resp) =
+                    handle_rename(&mut request, &parsed_filter, &document, &workspace)
+                {
+                    handle_request(connection, resp);
+                }
+                if let Some(resp) =
+                    handle_document_link(&mut request, &parsed_filter, &document, &document_path)
+                {
+                    handle_request(connection, resp);
+                }
+                if let Some(resp) =
+                    handle_document_highlight(&mut request, &parsed_filter, &document)
+                {
+                    handle_request(connection, resp);
+                }
+                if let Some(resp) = handle_code_action(&mut request, &parsed_filter, &document) {
+                    handle_request(connection, resp);
+                }
             }
             Message::Response(_resp) => {}
             Message::Notification(not) => {
                 let mut notification = NotMessage { not };
-                if let Some(parsed) = handle_save(&mut notification) {
-                    parsed_filter = parsed;
-                }
-                if let Some(parsed) = handle_change(&mut notification) {
-                    parsed_filter = parsed;
+                if let Ok(params) = notification.cast::<DidChangeWatchedFiles>() {
+                    for event in params.changes {
+                        // The open document is kept as the editor has it,
+                        // unsaved changes included.
+                        let path = match event.uri.to_file_path() {
+                            Ok(path) if tracked(&path) && path != document_path => path,
+                            _ => continue,
+                        };
+                        match fs::read_to_string(&path) {
+                            Ok(text) if event.typ != FileChangeType::Deleted => {
+                                index.update(workspace::update(&mut files, &path, &text))
+                            }
+                            _ => {
+                                workspace::remove(&mut files, &path);
+                                index.remove(&path);
+                            }
+                        }
+                    }
+                    imported = imports::load(&document_path, &parsed_filter);
+                    if let Ok(uri) = Url::from_file_path(&document_path) {
+                        publish_diagnostics(
+                            connection,
+                            uri,
+                            &document,
+                            &parsed_filter,
+                            &imported,
+                            &poe_data,
+                        );
+                    }
+                    continue;
                 }
-                if let Some(parsed) = handle_open(&mut notification) {
-                    parsed_filter = parsed;
+                // Imported files are only read again on open and save, not
+                // on every keystroke.
+                let (changed, reload) = match handle_save(&mut notification) {
+                    Some(saved) => (Some(saved), true),
+                    None => match handle_change(&mut notification) {
+                        Some(changed) => (Some(changed), false),
+                        None => (handle_open(&mut notification), true),
+                    },
+                };
+                if let Some((uri, text)) = changed {
+                    parsed_filter = mode_parsing::parse(&text);
+                    lenses = None;
+                    document_path = uri.to_file_path().unwrap_or_default();
+                    if reload {
+                        imported = imports::load(&document_path, &parsed_filter);
+                    }
+                    // The workspace copy and its symbols follow the saved
+                    // file, like those of the other files.
+                    if reload && tracked(&document_path) {
+                        index.update(workspace::update(&mut files, &document_path, &text));
+                    }
+                    publish_diagnostics(
+                        connection,
+                        uri,
+                        &text,
+                        &parsed_filter,
+                        &imported,
+                        &poe_data,
+                    );
+                    document = text;
                 }
             }
         }
@@ -152,6 +366,191 @@ fn main_loop(
     Ok(())
 }
 
+/// `filter_bin retier <filter> --tiers <config.csv> --prices-dir <dir> (--out <file> | --in-place)`
+/// rewrites the tier blocks of a filter into `--out`, or over the source only
+/// with `--in-place`, and prints the items that moved as JSON.
+fn retier() -> Result<(), Box<dyn Error + Sync + Send>> {
+    let usage = "usage: filter_bin retier <filter> --tiers <config.csv> --prices-dir <dir> (--out <file> | --in-place)";
+    let filter_path = std::env::args().nth(2).map(PathBuf::from).ok_or(usage)?;
+    let out = match (path_arg("--out"), flag("--in-place")) {
+        (Some(out), false) => out,
+        (None, true) => filter_path.clone(),
+        _ => return Err(usage.into()),
+    };
+    let config = tiering::TierConfig::from_file(&path_arg("--tiers").ok_or(usage)?)?;
+    let prices = economy::Prices::from_dir(&path_arg("--prices-dir").ok_or(usage)?)?;
+    let text = fs::read_to_string(&filter_path)?;
+
+    let tiering = tiering::retier(&text, &config, &prices);
+    fs::write(out, &tiering.text)?;
+    println!("{}", serde_json::to_string_pretty(&tiering)?);
+    Ok(())
+}
+
+/// `filter_bin strictness <filter> [--out-dir <dir>]` writes one filter per
+/// strictness level, like `name-3-strict.filter`, next to the source by default.
+fn strictness() -> Result<(), Box<dyn Error + Sync + Send>> {
+    let usage = "usage: filter_bin strictness <filter> [--out-dir <dir>]";
+    let filter_path = std::env::args().nth(2).map(PathBuf::from).ok_or(usage)?;
+    let text = fs::read_to_string(&filter_path)?;
+    let out_dir = path_arg("--out-dir")
+        .or_else(|| filter_path.parent().map(PathBuf::from))
+        .unwrap_or_default();
+    let stem = filter_path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    fs::create_dir_all(&out_dir)?;
+    for (level, (name, variant)) in strictness::variants(&text).into_iter().enumerate() {
+        let path = out_dir.join(format!("{}-{}-{}.filter", stem, level, name));
+        fs::write(&path, variant)?;
+        println!("{}", path.display());
+    }
+    Ok(())
+}
+
+/// `filter_bin style <filter> --style <file.style> [--out <file>]` renders the
+/// filter with the style's actions, next to the source as `name-style.filter`
+/// unless `--out` is given.
+fn style() -> Result<(), Box<dyn Error + Sync + Send>> {
+    let usage = "usage: filter_bin style <filter> --style <file.style> [--out <file>]";
+    let filter_path = std::env::args().nth(2).map(PathBuf::from).ok_or(usage)?;
+    let style_path = path_arg("--style").ok_or(usage)?;
+    let style = styles::Style::from_file(&style_path)?;
+    let text = fs::read_to_string(&filter_path)?;
+
+    let out = path_arg("--out").unwrap_or_else(|| {
+        let stem = |p: &PathBuf| {
+            p.file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        };
+        filter_path.with_file_name(format!(
+            "{}-{}.filter",
+            stem(&filter_path),
+            stem(&style_path)
+        ))
+    });
+    fs::write(&out, styles::render(&text, &style))?;
+    println!("{}", out.display());
+    Ok(())
+}
+
+/// `filter_bin extract-style <filter> --out <file.style> [--tagged <file>]` writes
+/// the filter's actions as a style file and a copy of the filter with its blocks
+/// tagged with the styles, next to the source as `name-tagged.filter` unless
+/// `--tagged` is given. The source is left alone.
+fn extract_style() -> Result<(), Box<dyn Error + Sync + Send>> {
+    let usage = "usage: filter_bin extract-style <filter> --out <file.style> [--tagged <file>]";
+    let filter_path = std::env::args().nth(2).map(PathBuf::from).ok_or(usage)?;
+    let out = path_arg("--out").ok_or(usage)?;
+    let text = fs::read_to_string(&filter_path)?;
+
+    let tagged_path = path_arg("--tagged").unwrap_or_else(|| {
+        let stem = filter_path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        filter_path.with_file_name(format!("{}-tagged.filter", stem))
+    });
+    let (style, tagged) = styles::extract(&text);
+    fs::write(&out, style.to_string())?;
+    fs::write(&tagged_path, tagged)?;
+    println!("{} styles", style.entries.len());
+    println!("{}", tagged_path.display());
+    Ok(())
+}
+
+/// `filter_bin build <filter> [--out <file>]` bundles the filter's imports and
+/// expands its mixins and variables into a plain filter the game can load, by default
+/// `name.build.filter`.
+fn build() -> Result<(), Box<dyn Error + Sync + Send>> {
+    let usage = "usage: filter_bin build <filter> [--out <file>]";
+    let filter_path = std::env::args().nth(2).map(PathBuf::from).ok_or(usage)?;
+    let text = imports::bundle(&filter_path)?;
+
+    let out = path_arg("--out").unwrap_or_else(|| filter_path.with_extension("build.filter"));
+    let text = mixins::expand(&text);
+    fs::write(&out, variables::expand(&text))?;
+    println!("{}", out.display());
+    Ok(())
+}
+
+/// A directory given on the command line, like `--data-dir <path>`.
+fn path_arg(flag: &str) -> Option<PathBuf> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == flag {
+            return args.next().map(PathBuf::from);
+        }
+    }
+    None
+}
+
+/// Whether a switch like `--in-place` is on the command line.
+fn flag(name: &str) -> bool {
+    std::env::args().skip(1).any(|arg| arg == name)
+}
+
+/// Asks the client to report `.filter` files created, changed or deleted on
+/// disk, which keeps the workspace symbols current.
+fn watch_filters(connection: &Connection) {
+    let options = DidChangeWatchedFilesRegistrationOptions {
+        watchers: vec![FileSystemWatcher {
+            glob_pattern: String::from("**/*.filter"),
+            kind: None,
+        }],
+    };
+    let params = RegistrationParams {
+        registrations: vec![Registration {
+            id: String::from("watch-filters"),
+            method: <DidChangeWatchedFiles as lsp_types::notification::Notification>::METHOD
+                .to_string(),
+            register_options: serde_json::to_value(options).ok(),
+        }],
+    };
+    let req = Request::new(
+        RequestId::from(String::from("watch-filters")),
+        <RegisterCapability as lsp_types::request::Request>::METHOD.to_string(),
+        params,
+    );
+    let _req = connection.sender.send(Message::Request(req));
+}
+
+fn show_message(connection: &Connection, typ: MessageType, message: String) {
+    let not = Notification::new(
+        <ShowMessage as lsp_types::notification::Notification>::METHOD.to_string(),
+        ShowMessageParams { typ, message },
+    );
+    let _not = connection.sender.send(Message::Notification(not));
+}
+
+fn publish_diagnostics(
+    connection: &Connection,
+    uri: Url,
+    text: &str,
+    parsed_filter: &mode_parsing::Filter,
+    imported: &[imports::Imported],
+    poe_data: &data_parsing::PoeData,
+) {
+    let path = uri.to_file_path().unwrap_or_default();
+    let not = Notification::new(
+        <PublishDiagnostics as lsp_types::notification::Notification>::METHOD.to_string(),
+        PublishDiagnosticsParams {
+            uri,
+            diagnostics: diagnostics::unknown_names(parsed_filter, text, poe_data)
+                .into_iter()
+                .chain(diagnostics::variables(parsed_filter, text, imported))
+                .chain(imports::problems(&path, parsed_filter, text, imported))
+                .chain(sounds::problems(&path, parsed_filter, text))
+                .collect(),
+            version: None,
+        },
+    );
+    let _not = connection.sender.send(Message::Notification(not));
+}
+
 fn handle_request(connection: &Connection, response: Response) {
     let _resp = connection.sender.send(Message::Response(response));
 }
@@ -159,10 +558,19 @@ fn handle_request(connection: &Connection, response: Response) {
 fn handle_hover(
     request: &mut ReqMessage,
     parsed_filter: &mode_parsing::Filter,
+    document: &str,
+    imported: &[imports::Imported],
+    poe_data: &data_parsing::PoeData,
 ) -> Option<Response> {
     if let Ok((id, params)) = request.cast::<HoverRequest>() {
         let result = Some(lsp_types::Hover {
-            contents: lsp_types::HoverContents::Array(hover::hover_keyword(params, parsed_filter)),
+            contents: lsp_types::HoverContents::Array(hover::hover_keyword(
+                params,
+                parsed_filter,
+                document,
+                imported,
+                poe_data,
+            )),
             range: None,
         });
         let result = serde_json::to_value(&result).unwrap();
@@ -176,15 +584,276 @@ fn handle_hover(
     None
 }
 
+fn handle_signature_help(request: &mut ReqMessage, document: &str) -> Option<Response> {
+    if let Ok((id, params)) = request.cast::<SignatureHelpRequest>() {
+        let result = signature_help::signature_help(params, document);
+        let result = serde_json::to_value(&result).unwrap();
+        let resp = Response {
+            id,
+            result: Some(result),
+            error: None,
+        };
+        return Some(resp);
+    }
+    None
+}
+
+fn handle_definition(
+    request: &mut ReqMessage,
+    parsed_filter: &mode_parsing::Filter,
+    document: &str,
+    imported: &[imports::Imported],
+) -> Option<Response> {
+    if let Ok((id, params)) = request.cast::<GotoDefinition>() {
+        let result = variables::goto_definition(params, parsed_filter, document, imported);
+        let result = serde_json::to_value(&result).unwrap();
+        return Some(Response {
+            id,
+            result: Some(result),
+            error: None,
+        });
+    }
+    None
+}
+
+/// The filters around the open document that references and renames reach.
+struct Workspace<'a> {
+    files: &'a [imports::Imported],
+    imported: &'a [imports::Imported],
+    document_path: &'a std::path::Path,
+}
+
+impl<'a> Workspace<'a> {
+    fn others(
+        &self,
+    ) -> impl Iterator<Item = (&'a std::path::Path, &'a mode_parsing::Filter, &'a str)> {
+        workspace::others(self.files, self.imported, self.document_path)
+    }
+}
+
+fn handle_references(
+    request: &mut ReqMessage,
+    parsed_filter: &mode_parsing::Filter,
+    document: &str,
+    workspace: &Workspace,
+) -> Option<Response> {
+    if let Ok((id, params)) = request.cast::<References>() {
+        let imported = workspace.imported;
+        let mut result = variables::references(params.clone(), parsed_filter, document, imported);
+        if result.is_empty() {
+            result = mentions::references(params, parsed_filter, document, workspace.others());
+        }
+        let result = serde_json::to_value(&result).unwrap();
+        return Some(Response {
+            id,
+            result: Some(result),
+            error: None,
+        });
+    }
+    None
+}
+
+/// Answers from `lenses` when the document has not changed since they were
+/// worked out.
+fn handle_code_lens(
+    request: &mut ReqMessage,
+    parsed_filter: &mode_parsing::Filter,
+    document: &str,
+    poe_data: &data_parsing::PoeData,
+    lenses: &mut Option<Vec<CodeLens>>,
+) -> Option<Response> {
+    if let Ok((id, params)) = request.cast::<CodeLensRequest>() {
+        let uri = params.text_document.uri;
+        let result = lenses
+            .get_or_insert_with(|| code_lens::code_lenses(&uri, parsed_filter, document, poe_data));
+        let result = serde_json::to_value(result).unwrap();
+        return Some(Response {
+            id,
+            result: Some(result),
+            error: None,
+        });
+    }
+    None
+}
+
+/// Runs `code_lens::SHOW_BASES`, showing the bases of the block on the line
+/// the lens passes along, in the document it names. That is the open document,
+/// a workspace file or, failing those, the file as it is on disk.
+fn handle_execute_command(
+    connection: &Connection,
+    request: &mut ReqMessage,
+    document: &str,
+    workspace: &Workspace,
+    poe_data: &data_parsing::PoeData,
+) -> Option<Response> {
+    if let Ok((id, params)) = request.cast::<ExecuteCommand>() {
+        let path = params
+            .arguments
+            .first()
+            .and_then(|uri| Url::parse(uri.as_str()?).ok()?.to_file_path().ok());
+        let line = params.arguments.get(1).and_then(|l| l.as_u64());
+        let text = path.and_then(|path| {
+            if path == workspace.document_path {
+                return Some(document.to_string());
+            }
+            match workspace.files.iter().find(|f| f.path == path) {
+                Some(file) => Some(file.text.clone()),
+                None => fs::read_to_string(&path).ok(),
+            }
+        });
+        if let (code_lens::SHOW_BASES, Some(line), Some(text)) =
+            (params.command.as_str(), line, text)
+        {
+            let filter = mode_parsing::parse(&text);
+            if let Some(message) = code_lens::bases_message(&filter, &text, line, poe_data) {
+                show_message(connection, MessageType::Info, message);
+            }
+        }
+        return Some(Response {
+            id,
+            result: Some(serde_json::Value::Null),
+            error: None,
+        });
+    }
+    None
+}
+
+fn handle_inlay_hint(
+    request: &mut ReqMessage,
+    parsed_filter: &mode_parsing::Filter,
+    document: &str,
+    poe_data: &data_parsing::PoeData,
+) -> Option<Response> {
+    if let Ok((id, params)) = request.cast::<inlay_hints::InlayHintRequest>() {
+        let result = inlay_hints::inlay_hints(params.range, parsed_filter, document, poe_data);
+        let result = serde_json::to_value(&result).unwrap();
+        return Some(Response {
+            id,
+            result: Some(result),
+            error: None,
+        });
+    }
+    None
+}
+
+fn handle_workspace_symbol(
+    request: &mut ReqMessage,
+    index: &symbols::SymbolIndex,
+) -> Option<Response> {
+    if let Ok((id, params)) = request.cast::<WorkspaceSymbol>() {
+        let result = index.search(&params.query);
+        let result = serde_json::to_value(&result).unwrap();
+        return Some(Response {
+            id,
+            result: Some(result),
+            error: None,
+        });
+    }
+    None
+}
+
+fn handle_prepare_rename(
+    request: &mut ReqMessage,
+    parsed_filter: &mode_parsing::Filter,
+    document: &str,
+) -> Option<Response> {
+    if let Ok((id, params)) = request.cast::<PrepareRenameRequest>() {
+        let result = rename::prepare_rename(params, parsed_filter, document);
+        let result = serde_json::to_value(&result).unwrap();
+        return Some(Response {
+            id,
+            result: Some(result),
+            error: None,
+        });
+    }
+    None
+}
+
+fn handle_rename(
+    request: &mut ReqMessage,
+    parsed_filter: &mode_parsing::Filter,
+    document: &str,
+    workspace: &Workspace,
+) -> Option<Response> {
+    if let Ok((id, params)) = request.cast::<Rename>() {
+        return Some(
+            match rename::rename(params, parsed_filter, document, workspace.others()) {
+                Ok(result) => Response::new_ok(id, result),
+                Err(message) => Response::new_err(id, ErrorCode::InvalidParams as i32, message),
+            },
+        );
+    }
+    None
+}
+
+fn handle_document_highlight(
+    request: &mut ReqMessage,
+    parsed_filter: &mode_parsing::Filter,
+    document: &str,
+) -> Option<Response> {
+    if let Ok((id, params)) = request.cast::<DocumentHighlightRequest>() {
+        let result = mentions::highlights(params, parsed_filter, document);
+        let result = serde_json::to_value(&result).unwrap();
+        return Some(Response {
+            id,
+            result: Some(result),
+            error: None,
+        });
+    }
+    None
+}
+
+fn handle_document_link(
+    request: &mut ReqMessage,
+    parsed_filter: &mode_parsing::Filter,
+    document: &str,
+    document_path: &std::path::Path,
+) -> Option<Response> {
+    if let Ok((id, _params)) = request.cast::<DocumentLinkRequest>() {
+        let mut result = imports::document_links(document_path, parsed_filter, document);
+        result.extend(sounds::document_links(
+            document_path,
+            parsed_filter,
+            document,
+        ));
+        let result = serde_json::to_value(&result).unwrap();
+        return Some(Response {
+            id,
+            result: Some(result),
+            error: None,
+        });
+    }
+    None
+}
+
+fn handle_code_action(
+    request: &mut ReqMessage,
+    parsed_filter: &mode_parsing::Filter,
+    document: &str,
+) -> Option<Response> {
+    if let Ok((id, params)) = request.cast::<CodeActionRequest>() {
+        let result = mixins::extract(&params, parsed_filter, document);
+        let result = serde_json::to_value(&result).unwrap();
+        return Some(Response {
+            id,
+            result: Some(result),
+            error: None,
+        });
+    }
+    None
+}
+
 // TODO swap vec![] with completion function
 fn handle_completion(
     request: &mut ReqMessage,
     parsed_filter: &mode_parsing::Filter,
-    poe_data: data_parsing::PoeData,
+    document: &str,
+    poe_data: &data_parsing::PoeData,
+    snippets: bool,
 ) -> Option<Response> {
     if let Ok((id, params)) = request.cast::<Completion>() {
-        if let Ok(json) = serde_json::to_value(&CompletionResponse::Array(
-            completion::completion_parse(params, parsed_filter, poe_data),
+        if let Ok(json) = serde_json::to_value(CompletionResponse::Array(
+            completion::completion_parse(params, parsed_filter, document, poe_data, snippets),
         )) {
             return Some(Response {
                 id,
@@ -196,29 +865,29 @@ fn handle_completion(
     None
 }
 
-fn handle_change(notification: &mut NotMessage) -> Option<mode_parsing::Filter> {
+fn handle_change(notification: &mut NotMessage) -> Option<(Url, String)> {
     if let Ok(params) = notification.cast::<DidChangeTextDocument>() {
         let filter_file = params.content_changes;
-        let text = &filter_file[0].text;
-        return Some(mode_parsing::parse(text));
+        let text = filter_file[0].text.clone();
+        return Some((params.text_document.uri, text));
     }
     None
 }
-fn handle_save(notification: &mut NotMessage) -> Option<mode_parsing::Filter> {
+fn handle_save(notification: &mut NotMessage) -> Option<(Url, String)> {
     if let Ok(params) = notification.cast::<DidSaveTextDocument>() {
         if let Ok(path) = params.text_document.uri.to_file_path() {
             if let Ok(text) = fs::read_to_string(&path) {
-                return Some(mode_parsing::parse(&text));
+                return Some((params.text_document.uri, text));
             }
         }
     }
     None
 }
-fn handle_open(notification: &mut NotMessage) -> Option<mode_parsing::Filter> {
+fn handle_open(notification: &mut NotMessage) -> Option<(Url, String)> {
     if let Ok(params) = notification.cast::<DidOpenTextDocument>() {
         if let Ok(path) = params.text_document.uri.to_file_path() {
             if let Ok(text) = fs::read_to_string(&path) {
-                return Some(mode_parsing::parse(&text));
+                return Some((params.text_document.uri, text));
             }
         }
     }
@@ -0,0 +1,280 @@
+use crate::data_parsing::{BaseItem, PoeData};
+use crate::hover::LineStarts;
+use crate::mixins;
+use crate::mode_parsing::{parse, Filter, FilterBlock, Token};
+use crate::variables;
+use lsp_types::*;
+use std::borrow::Cow;
+use std::collections::HashSet;
+
+/// The command a block's code lens runs to list the bases it matches.
+pub const SHOW_BASES: &str = "poe-filter.showBases";
+
+/// Conditions decided by the base alone; a block with only these catches
+/// every item of its bases.
+const BASE_CONDITIONS: &[Token] = &[
+    Token::Class,
+    Token::BaseType,
+    Token::DropLevel,
+    Token::Width,
+    Token::Height,
+];
+
+/// How many bases `bases_message` names before summing up the rest.
+const MAX_LISTED: usize = 20;
+
+/// The known bases a block can match, going by `possible_bases` and its
+/// `BaseType`, `Width` and `Height` conditions.
+pub fn matching_bases<'a>(block: &FilterBlock, poe_data: &'a PoeData) -> Vec<&'a BaseItem> {
+    // Each `BaseType` line as the ids of the bases it matches.
+    let named = block
+        .keywords
+        .iter()
+        .filter(|line| line.token == Token::BaseType)
+        .map(|line| {
+            let exact = line.operator() == Some("==");
+            line.strings()
+                .into_iter()
+                .flat_map(|s| poe_data.bases().matching(s, exact))
+                .map(|base| base.id)
+                .collect::<HashSet<_>>()
+        })
+        .collect::<Vec<_>>();
+    // Only the bases of the narrowest `BaseType` line need checking.
+    let bases = match named.iter().min_by_key(|ids| ids.len()) {
+        Some(ids) => {
+            let mut bases = ids
+                .iter()
+                .filter_map(|id| poe_data.base(*id))
+                .collect::<Vec<_>>();
+            bases.sort_by_key(|base| base.id);
+            block.possible_among(bases, poe_data)
+        }
+        None => block.possible_bases(poe_data),
+    };
+    bases
+        .into_iter()
+        .filter(|base| named.iter().all(|ids| ids.contains(&base.id)))
+        .filter(|base| {
+            block.keywords.iter().all(|line| match line.token {
+                Token::Width => base.width.is_none_or(|w| line.matches_number(w)),
+                Token::Height => base.height.is_none_or(|h| line.matches_number(h)),
+                _ => true,
+            })
+        })
+        .collect()
+}
+
+/// The blocks of `filter` as the game loads them, with variables and mixins
+/// expanded, in the same order. The blocks as written when expanding does not
+/// keep one block for each.
+fn expanded<'a>(filter: &'a Filter, text: &str) -> Cow<'a, Filter> {
+    if filter.variables.is_empty() && filter.mixins.is_empty() {
+        return Cow::Borrowed(filter);
+    }
+    let expanded = parse(&variables::expand(&mixins::expand(text)));
+    if expanded.vec.len() == filter.vec.len() {
+        Cow::Owned(expanded)
+    } else {
+        Cow::Borrowed(filter)
+    }
+}
+
+/// A variable or mixin the expanded block still uses, which is not defined
+/// in this file; its bases cannot be known.
+fn undefined(block: &FilterBlock) -> Option<&str> {
+    block.keywords.iter().find_map(|line| {
+        std::iter::once(&line.token)
+            .chain(line.value.iter().map(|v| &v.token))
+            .find_map(|token| match token {
+                Token::Variable(name) => Some(name.as_str()),
+                _ => None,
+            })
+    })
+}
+
+/// What a lens knows about a block: the bases it matches, or the undefined
+/// `$name` that keeps them from being known.
+#[derive(Clone)]
+enum Bases<'a> {
+    Known(Vec<&'a BaseItem>),
+    Unknown(String),
+}
+
+fn bases_of<'a>(block: &FilterBlock, poe_data: &'a PoeData) -> Bases<'a> {
+    match undefined(block) {
+        Some(name) => Bases::Unknown(name.to_string()),
+        None => Bases::Known(matching_bases(block, poe_data)),
+    }
+}
+
+/// The `Show` and `Hide` blocks with, for each, the header of an earlier block
+/// that already catches every base it matches. Blocks followed by `Continue`
+/// or with conditions beyond the base never catch everything. Bases are
+/// matched against the blocks with their variables and mixins expanded.
+fn blocks_with_shadows<'a>(
+    filter: &'a Filter,
+    text: &str,
+    poe_data: &'a PoeData,
+) -> Vec<(&'a FilterBlock, Bases<'a>, Option<usize>)> {
+    let resolved = expanded(filter, text);
+    // Each block's bases, worked out once for its own lens and the later ones.
+    let all = filter
+        .vec
+        .iter()
+        .zip(resolved.vec.iter())
+        .map(|(block, resolved)| {
+            matches!(block.block, Some(Token::Show) | Some(Token::Hide))
+                .then(|| bases_of(resolved, poe_data))
+        })
+        .collect::<Vec<_>>();
+    let mut caught = HashSet::<i32>::new();
+    let mut out = vec![];
+    for (i, (block, bases)) in filter.vec.iter().zip(all.iter()).enumerate() {
+        let bases = match bases {
+            Some(bases) => bases,
+            None => continue,
+        };
+        let shadow = match bases {
+            Bases::Known(bases)
+                if !bases.is_empty() && bases.iter().all(|b| caught.contains(&b.id)) =>
+            {
+                shadowing(filter, &all[..i], bases)
+            }
+            _ => None,
+        };
+        let continues = filter
+            .vec
+            .get(i + 1)
+            .is_some_and(|next| next.block == Some(Token::Continue));
+        let by_base = resolved.vec[i]
+            .keywords
+            .iter()
+            .all(|line| BASE_CONDITIONS.contains(&line.token));
+        if let (Bases::Known(bases), true, false) = (bases, by_base, continues) {
+            caught.extend(bases.iter().map(|b| b.id));
+        }
+        out.push((block, bases.clone(), shadow));
+    }
+    out
+}
+
+/// The last of the blocks before, whose bases are `earlier`, catching one
+/// of `bases`, to point at.
+fn shadowing(filter: &Filter, earlier: &[Option<Bases>], bases: &[&BaseItem]) -> Option<usize> {
+    let ids = bases.iter().map(|b| b.id).collect::<HashSet<_>>();
+    filter.vec[..earlier.len()]
+        .iter()
+        .zip(earlier.iter())
+        .rev()
+        .find(|(_, theirs)| match theirs {
+            Some(Bases::Known(theirs)) => theirs.iter().any(|t| ids.contains(&t.id)),
+            _ => false,
+        })
+        .map(|(b, _)| b.bspan.start)
+}
+
+fn count(n: usize, noun: &str) -> String {
+    match (n, noun.ends_with('s')) {
+        (1, _) => format!("1 {}", noun),
+        (_, true) => format!("{} {}es", n, noun),
+        _ => format!("{} {}s", n, noun),
+    }
+}
+
+/// A lens above each `Show` and `Hide` block with how many bases and
+/// classes it can match, their drop levels and whether an earlier block
+/// already takes all of them. Clicking it lists the bases.
+pub fn code_lenses(uri: &Url, filter: &Filter, text: &str, poe_data: &PoeData) -> Vec<CodeLens> {
+    let lines = LineStarts::new(text);
+    blocks_with_shadows(filter, text, poe_data)
+        .into_iter()
+        .map(|(block, bases, shadow)| {
+            let bases = match bases {
+                Bases::Known(bases) => bases,
+                Bases::Unknown(name) => {
+                    return lens(
+                        uri,
+                        block,
+                        &lines,
+                        format!("bases unknown, {} is not defined here", name),
+                    )
+                }
+            };
+            let classes = bases
+                .iter()
+                .filter_map(|b| b.class)
+                .collect::<HashSet<_>>()
+                .len();
+            let levels = bases.iter().filter_map(|b| b.drop_level);
+            let mut title = match (levels.clone().min(), levels.max()) {
+                _ if bases.is_empty() => String::from("matches no known bases"),
+                (Some(min), Some(max)) => format!(
+                    "{} · {} · drop level {}–{}",
+                    count(bases.len(), "base"),
+                    count(classes, "class"),
+                    min,
+                    max
+                ),
+                _ => format!(
+                    "{} · {}",
+                    count(bases.len(), "base"),
+                    count(classes, "class")
+                ),
+            };
+            match shadow {
+                Some(at) => title.push_str(&format!(
+                    " · unreachable, caught on line {}",
+                    lines.position(at).line + 1
+                )),
+                None if bases.is_empty() => {}
+                None => title.push_str(" · reachable"),
+            }
+            lens(uri, block, &lines, title)
+        })
+        .collect()
+}
+
+fn lens(uri: &Url, block: &FilterBlock, lines: &LineStarts, title: String) -> CodeLens {
+    let start = lines.position(block.bspan.start);
+    CodeLens {
+        range: Range::new(start, lines.position(block.bspan.end)),
+        command: Some(Command {
+            title,
+            command: SHOW_BASES.to_string(),
+            arguments: Some(vec![
+                serde_json::json!(uri.as_str()),
+                serde_json::json!(start.line),
+            ]),
+        }),
+        data: None,
+    }
+}
+
+/// The bases the block whose header is on `line` matches, for `SHOW_BASES`.
+/// Past the first few, the rest are only counted.
+pub fn bases_message(filter: &Filter, text: &str, line: u64, poe_data: &PoeData) -> Option<String> {
+    let lines = LineStarts::new(text);
+    let at = filter
+        .vec
+        .iter()
+        .position(|b| lines.position(b.bspan.start).line == line)?;
+    let resolved = expanded(filter, text);
+    let bases = match bases_of(&resolved.vec[at], poe_data) {
+        Bases::Known(bases) => bases,
+        Bases::Unknown(name) => return Some(format!("{} is not defined here", name)),
+    };
+    let mut names = bases
+        .iter()
+        .take(MAX_LISTED)
+        .map(|b| b.name.clone())
+        .collect::<Vec<_>>();
+    if bases.len() > MAX_LISTED {
+        names.push(format!("and {} more", bases.len() - MAX_LISTED));
+    }
+    Some(format!(
+        "{}: {}",
+        count(bases.len(), "base"),
+        names.join(", ")
+    ))
+}
@@ -1,81 +1,80 @@
 use crate::data_parsing;
 use crate::hover;
 use crate::mode_parsing;
+use crate::mode_parsing::Token;
+use crate::sounds;
 // use log::info;
+use logos::Logos;
 use lsp_types::*;
-use std::fs;
-use std::path::PathBuf;
 
+/// Completions at the cursor. `string` is the document as the editor has it,
+/// which `filter` was parsed from; the copy on disk may be older.
 pub fn completion_parse(
     params: CompletionParams,
     filter: &mode_parsing::Filter,
-    poe_data: data_parsing::PoeData,
+    string: &str,
+    poe_data: &data_parsing::PoeData,
+    snippets: bool,
 ) -> Vec<CompletionItem> {
-    if let Some((string, _path)) = get_string_and_path(&params) {
-        let byte = hover::byte_pos_in_string(
-            params.text_document_position.position.line as usize,
-            params.text_document_position.position.character as usize,
-            string,
-        );
-
-        if let Some(context) = params.context {
-            if let Some(character) = context.trigger_character {
-                // info!("{:?}", character);
-                if character == "\"".to_string() {
-                    if let Some(block) = filter.search_block(byte) {
-                        if let Some(keyword) = block.search_keyword(byte) {
-                            if let Some(list) = keyword.valid_values(poe_data) {
-                                let completion = list
-                                    .iter()
-                                    .filter_map(|r| r.name.clone())
-                                    .map(|n| CompletionItem {
-                                        label: n,
-                                        kind: Some(CompletionItemKind::Value),
-                                        ..CompletionItem::default()
-                                    })
-                                    .collect::<Vec<_>>();
-                                return completion;
-                            }
-                        }
-                    }
-                }
-            }
+    let position = params.text_document_position.position;
+    let byte = hover::byte_pos_in_string(
+        position.line as usize,
+        position.character as usize,
+        string.to_string(),
+    );
+    if let Some(context) =
+        argument_context(string, position.line as usize, position.character as usize)
+    {
+        if context.keyword == Token::CustomAlertSound && context.in_quotes {
+            let path = match params
+                .text_document_position
+                .text_document
+                .uri
+                .to_file_path()
+            {
+                Ok(path) => path,
+                Err(_) => return vec![],
+            };
+            let line = string.lines().nth(position.line as usize).unwrap_or("");
+            let prefix = line.get(..position.character as usize).unwrap_or(line);
+            let typed = &prefix[prefix.rfind('"').map_or(0, |i| i + 1)..];
+            return sounds::completion(&path, typed);
         }
+        return argument_completion(&context, filter.search_block(byte), poe_data);
+    }
 
-        if let Some(block) = filter.search_block(byte) {
-            if let Some(keyword) = block.search_keyword(byte) {
-                if let Some(ktype) = keyword.keyword_type() {
-                    let list: Vec<mode_parsing::Token> = match ktype {
-                        mode_parsing::KeywordType::Conditions => {
-                            let cond = ktype.token_list();
-                            let act = mode_parsing::KeywordType::Actions.token_list();
-                            let out = cond.into_iter().chain(act.into_iter()).collect::<Vec<_>>();
-                            out
-                        }
-                        mode_parsing::KeywordType::Actions => ktype.token_list(),
-                        mode_parsing::KeywordType::Block => {
-                            mode_parsing::KeywordType::Conditions.token_list()
-                        }
-                        mode_parsing::KeywordType::Operations => vec![],
-                        mode_parsing::KeywordType::Values(_) => vec![],
-                    };
-                    let out = list
-                        .into_iter()
-                        .chain(mode_parsing::KeywordType::Block.token_list().into_iter())
-                        .map(|t| t.create_completion_item())
-                        .collect::<Vec<_>>();
-                    return out;
-                }
-            } else {
-                let block = mode_parsing::KeywordType::Block.token_list();
-                let cond = mode_parsing::KeywordType::Conditions.token_list();
-                let out = block
+    if let Some(block) = filter.search_block(byte) {
+        if let Some(keyword) = block.search_keyword(byte) {
+            if let Some(ktype) = keyword.keyword_type() {
+                let list: Vec<mode_parsing::Token> = match ktype {
+                    mode_parsing::KeywordType::Conditions => {
+                        let cond = ktype.token_list();
+                        let act = mode_parsing::KeywordType::Actions.token_list();
+                        cond.into_iter().chain(act).collect::<Vec<_>>()
+                    }
+                    mode_parsing::KeywordType::Actions => ktype.token_list(),
+                    mode_parsing::KeywordType::Block => {
+                        mode_parsing::KeywordType::Conditions.token_list()
+                    }
+                    mode_parsing::KeywordType::Operations => vec![],
+                    mode_parsing::KeywordType::Values(_) => vec![],
+                };
+                let out = list
                     .into_iter()
-                    .chain(cond.into_iter())
-                    .map(|t| t.create_completion_item())
+                    .chain(mode_parsing::KeywordType::Block.token_list())
+                    .map(|t| t.create_completion_item(snippets))
                     .collect::<Vec<_>>();
                 return out;
             }
+        } else {
+            let block = mode_parsing::KeywordType::Block.token_list();
+            let cond = mode_parsing::KeywordType::Conditions.token_list();
+            let out = block
+                .into_iter()
+                .chain(cond)
+                .map(|t| t.create_completion_item(snippets))
+                .collect::<Vec<_>>();
+            return out;
         }
     }
 
@@ -83,28 +82,121 @@ pub fn completion_parse(
     let cond = mode_parsing::KeywordType::Conditions.token_list();
     let act = mode_parsing::KeywordType::Actions.token_list();
 
-    let out = block
+    block
         .into_iter()
-        .chain(cond.into_iter())
-        .chain(act.into_iter())
-        .map(|t| t.create_completion_item())
+        .chain(cond)
+        .chain(act)
+        .map(|t| t.create_completion_item(snippets))
+        .collect::<Vec<_>>()
+}
+
+/// The argument slot the cursor is in on a condition or action line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArgumentContext {
+    pub keyword: Token,
+    /// Zero-based index of the argument being typed, not counting the operator.
+    pub index: usize,
+    pub operator: bool,
+    pub in_quotes: bool,
+}
+
+/// Works out which argument of which keyword is being typed at `line`/`character`.
+/// Returns `None` when the cursor is not past a condition or action keyword.
+pub fn argument_context(string: &str, line: usize, character: usize) -> Option<ArgumentContext> {
+    let line = string.lines().nth(line).unwrap_or("");
+    let prefix = line.get(..character.min(line.len()))?;
+    let in_quotes = prefix.matches('"').count() % 2 == 1;
+    let (prefix, typing) = if in_quotes {
+        (&prefix[..prefix.rfind('"').unwrap_or(0)], false)
+    } else {
+        (prefix, !prefix.ends_with(char::is_whitespace))
+    };
+
+    let mut tokens = Token::lexer(prefix)
+        .filter(|t| *t != Token::Error && *t != Token::Hash)
         .collect::<Vec<_>>();
-    out
+    if typing {
+        tokens.pop();
+    }
+    let mut tokens = tokens.into_iter();
+    let keyword = tokens.next()?;
+    match keyword.keyword_type() {
+        Some(mode_parsing::KeywordType::Conditions) | Some(mode_parsing::KeywordType::Actions) => {}
+        _ => return None,
+    }
+    let (operators, values): (Vec<_>, Vec<_>) =
+        tokens.partition(|t| matches!(t, Token::Operator(_)));
+    Some(ArgumentContext {
+        keyword,
+        index: values.len(),
+        operator: !operators.is_empty(),
+        in_quotes,
+    })
 }
 
-pub fn get_string_and_path(params: &CompletionParams) -> Option<(String, PathBuf)> {
-    if let Ok(path) = params
-        .text_document_position
-        .text_document
-        .uri
-        .to_file_path()
-    {
-        if let Ok(string) = fs::read_to_string(&path) {
-            Some((string, path))
+pub fn argument_completion(
+    context: &ArgumentContext,
+    block: Option<&mode_parsing::FilterBlock>,
+    poe_data: &data_parsing::PoeData,
+) -> Vec<CompletionItem> {
+    let quoted = match context.keyword {
+        Token::Class
+        | Token::BaseType
+        | Token::HasExplicitMod
+        | Token::Prophecy
+        | Token::HasEnchantment => !context.in_quotes,
+        _ => false,
+    };
+    if context.keyword == Token::BaseType && (context.in_quotes || quoted) {
+        let bases = match block {
+            Some(block) => block.possible_bases(poe_data),
+            None => poe_data.bases().items().iter().collect(),
+        };
+        return bases
+            .into_iter()
+            .map(|base| {
+                let detail = match (poe_data.class_of(base), base.drop_level) {
+                    (Some(class), Some(level)) => {
+                        Some(format!("{} - DropLevel {}", class.name, level))
+                    }
+                    _ => None,
+                };
+                value_completion_item(base.name.clone(), detail, quoted)
+            })
+            .collect::<Vec<_>>();
+    }
+    if context.in_quotes || quoted {
+        if let Some(list) = context.keyword.valid_values(poe_data) {
+            return list
+                .map(|n| value_completion_item(n.to_string(), None, quoted))
+                .collect::<Vec<_>>();
+        }
+    }
+    context
+        .keyword
+        .argument_values(context.index, context.operator)
+        .iter()
+        .map(|t| CompletionItem {
+            label: t.label(),
+            kind: match t {
+                Token::Operator(_) => Some(CompletionItemKind::Operator),
+                _ => Some(CompletionItemKind::EnumMember),
+            },
+            ..CompletionItem::default()
+        })
+        .collect::<Vec<_>>()
+}
+
+fn value_completion_item(name: String, detail: Option<String>, quoted: bool) -> CompletionItem {
+    CompletionItem {
+        insert_text: if quoted {
+            Some(format!("\"{}\"", name))
         } else {
             None
-        }
-    } else {
-        None
+        },
+        label: name,
+        kind: Some(CompletionItemKind::Value),
+        detail,
+        ..CompletionItem::default()
     }
 }
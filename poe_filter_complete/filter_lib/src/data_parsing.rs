@@ -1,68 +1,925 @@
 // use csv::*;
+use crate::economy::Prices;
+use crate::lookup::{NameIndex, Table};
 use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
 
-#[derive(Deserialize, Default, Clone, Debug)]
+#[derive(Default, Clone, Debug)]
+/// The game data tables. They are only filled by the loaders, which build
+/// the name index with them; read them through `classes()`, `bases()` and
+/// the other `Table` accessors.
 pub struct PoeData {
-    pub classes: Vec<Record>,
-    pub bases: Vec<Record>,
-    pub mods: Vec<Record>,
-}
-#[derive(Deserialize, Default, Clone, Debug)]
-pub struct Record {
-    pub id: Option<i32>,
-    pub name: Option<String>,
+    classes: Vec<ItemClass>,
+    bases: Vec<BaseItem>,
+    mods: Vec<Mod>,
+    gems: Vec<Gem>,
+    prophecies: Vec<Prophecy>,
+    enchantments: Vec<Enchantment>,
+    uniques: Vec<UniqueItem>,
+    divination_cards: Vec<DivinationCard>,
+    /// Prices from local snapshots, empty unless some were loaded.
+    pub prices: Prices,
+    /// The data files that came from the bundled data rather than a data
+    /// directory.
+    bundled: Vec<&'static str>,
+    index: DataIndex,
+}
+
+#[derive(Default, Clone, Debug)]
+struct DataIndex {
+    classes: NameIndex,
+    bases: NameIndex,
+    mods: NameIndex,
+    gems: NameIndex,
+    prophecies: NameIndex,
+    enchantments: NameIndex,
+    uniques: NameIndex,
+    divination_cards: NameIndex,
+    /// Positions in `classes` by class id.
+    class_ids: HashMap<i32, usize>,
+    /// Positions in `mods` by mod id.
+    mod_ids: HashMap<String, usize>,
+    /// Positions in `uniques` by the id of their base.
+    uniques_by_base: HashMap<i32, Vec<usize>>,
+    /// Positions in `divination_cards` by the id of their base.
+    cards_by_base: HashMap<i32, usize>,
+}
+
+#[derive(Deserialize, Default, Clone, Debug, PartialEq)]
+pub struct ItemClass {
+    pub id: i32,
+    pub name: String,
     pub category: Option<String>,
+}
+
+#[derive(Deserialize, Default, Clone, Debug, PartialEq)]
+pub struct BaseItem {
+    /// Position in `PoeData::bases`.
+    #[serde(skip)]
+    pub id: i32,
+    pub name: String,
+    /// Id of the `ItemClass`.
+    pub class: Option<i32>,
+    pub domain: Option<i32>,
+    #[serde(rename = "droplevel")]
+    pub drop_level: Option<i32>,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    #[serde(skip)]
+    pub tags: Vec<String>,
+    /// Ids of the implicit `Mod`s.
+    #[serde(skip)]
+    pub implicits: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Affix {
+    Prefix,
+    Suffix,
+}
+
+#[derive(Deserialize, Default, Clone, Debug, PartialEq)]
+pub struct Mod {
+    #[serde(skip)]
+    pub id: String,
+    pub name: String,
     pub domain: Option<i32>,
-    pub droplevel: Option<i32>,
-}
-
-// #[derive(Deserialize, Debug)]
-// pub struct ItemClass {
-// 	pub id: Option<i32>,
-// 	pub name: Option<String>,
-// 	pub category: Option<String>,
-// }
-// #[derive(Deserialize, Debug)]
-// pub struct BaseType {
-// 	pub name: Option<String>,
-// 	pub class: Option<i32>,
-// 	pub domain: Option<i32>,
-// 	pub droplevel: Option<i32>,
-// }
-// #[derive(Deserialize, Debug)]
-// pub struct Mod {
-// 	pub domain: Option<i32>,
-// 	pub name: Option<String>,
-// }
+    #[serde(skip)]
+    pub affix: Option<Affix>,
+    pub generation_type: Option<String>,
+    /// Rank within the mod's group, 1 being the highest roll.
+    pub tier: Option<i32>,
+    pub required_level: Option<i32>,
+    #[serde(skip)]
+    pub stats: Vec<ModStat>,
+    /// Base item tags the mod can roll on.
+    #[serde(skip)]
+    pub spawn_tags: Vec<String>,
+}
+
+#[derive(Deserialize, Default, Clone, Debug, PartialEq)]
+pub struct ModStat {
+    pub id: String,
+    pub min: i32,
+    pub max: i32,
+}
+
+#[derive(Deserialize, Default, Clone, Debug, PartialEq)]
+pub struct Gem {
+    #[serde(default)]
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub support: bool,
+    #[serde(skip)]
+    pub tags: Vec<String>,
+}
+
+#[derive(Deserialize, Default, Clone, Debug, PartialEq)]
+pub struct Prophecy {
+    #[serde(default)]
+    pub id: i32,
+    pub name: String,
+}
+
+#[derive(Deserialize, Default, Clone, Debug, PartialEq)]
+pub struct Enchantment {
+    #[serde(default)]
+    pub id: i32,
+    pub name: String,
+    /// Id of the `ItemClass` the enchantment is applied to.
+    pub class: Option<i32>,
+}
+
+#[derive(Deserialize, Default, Clone, Debug, PartialEq)]
+pub struct UniqueItem {
+    #[serde(default)]
+    pub id: i32,
+    pub name: String,
+    /// Id of the `BaseItem`.
+    pub base: Option<i32>,
+    #[serde(default)]
+    pub drop_disabled: bool,
+}
+
+#[derive(Deserialize, Default, Clone, Debug, PartialEq)]
+pub struct DivinationCard {
+    /// Id of the card's `BaseItem`.
+    #[serde(default)]
+    pub id: i32,
+    pub name: String,
+    pub stack_size: Option<i32>,
+    pub reward: Option<String>,
+}
+
+/// A base type holding valuable uniques, see `PoeData::unique_tier`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UniqueBase<'a> {
+    pub base: &'a BaseItem,
+    pub valuable: Vec<&'a UniqueItem>,
+    /// Other droppable uniques on the same base, which the block will also show.
+    pub others: Vec<&'a UniqueItem>,
+}
+
+const CLASSES_FILE: &str = "itemclasses.csv";
+const BASES_FILE: &str = "baseitems.csv";
+const MODS_FILE: &str = "mods.csv";
+const PROPHECIES_FILE: &str = "prophecies.csv";
+const ENCHANTMENTS_FILE: &str = "enchantments.csv";
+const UNIQUES_FILE: &str = "uniques.csv";
+const CARDS_FILE: &str = "divination_cards.csv";
+const CSV_FILES: &[&str] = &[
+    CLASSES_FILE,
+    BASES_FILE,
+    MODS_FILE,
+    PROPHECIES_FILE,
+    ENCHANTMENTS_FILE,
+    UNIQUES_FILE,
+    CARDS_FILE,
+];
+const REPOE_CLASSES_FILE: &str = "item_classes.json";
+const REPOE_BASES_FILE: &str = "base_items.json";
+const REPOE_MODS_FILE: &str = "mods.json";
+const REPOE_GEMS_FILE: &str = "gems.json";
+const BUNDLED_CLASSES: &str = include_str!("test_filters/itemclasses.csv");
+const BUNDLED_BASES: &str = include_str!("test_filters/baseitems.csv");
+const BUNDLED_MODS: &str = include_str!("test_filters/mods.csv");
+const BUNDLED_PROPHECIES: &str = include_str!("test_filters/prophecies.csv");
+const BUNDLED_ENCHANTMENTS: &str = include_str!("test_filters/enchantments.csv");
+const BUNDLED_UNIQUES: &str = include_str!("test_filters/uniques.csv");
+const BUNDLED_CARDS: &str = include_str!("test_filters/divination_cards.csv");
+/// Bundled catalogs holding only a sample of the game's names.
+const SAMPLE_FILES: &[&str] = &[ENCHANTMENTS_FILE, UNIQUES_FILE];
+
+/// Alternate quality types accepted by `GemQualityType`.
+pub const QUALITY_TYPES: &[&str] = &["Superior", "Anomalous", "Divergent", "Phantasmal"];
+
+/// Why game data could not be loaded.
+#[derive(Debug)]
+pub enum DataError {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    Csv {
+        file: String,
+        error: csv::Error,
+    },
+    Json {
+        file: String,
+        error: serde_json::Error,
+    },
+    /// A line of a hand-written file, like a style file, that makes no sense.
+    Syntax {
+        file: String,
+        line: usize,
+        message: String,
+    },
+    /// A data directory without files it needs.
+    Missing {
+        dir: PathBuf,
+        files: Vec<String>,
+    },
+}
+
+impl fmt::Display for DataError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DataError::Io { path, error } => {
+                write!(f, "could not read {}: {}", path.display(), error)
+            }
+            DataError::Csv { file, error } => write!(f, "could not parse {}: {}", file, error),
+            DataError::Json { file, error } => write!(f, "could not parse {}: {}", file, error),
+            DataError::Syntax {
+                file,
+                line,
+                message,
+            } => write!(f, "could not parse {} line {}: {}", file, line, message),
+            DataError::Missing { dir, files } => {
+                write!(f, "{} has no {}", dir.display(), files.join(" or "))
+            }
+        }
+    }
+}
+
+impl Error for DataError {}
 
 impl PoeData {
+    /// Game data from the CSV files bundled with the server.
     pub fn new() -> Self {
-        let mut poe_data = PoeData {
-            classes: vec![],
-            bases: vec![],
-            mods: vec![],
+        PoeData::from_csv(BUNDLED_CLASSES, BUNDLED_BASES, BUNDLED_MODS)
+            .and_then(|data| data.with_catalogs(BUNDLED_PROPHECIES, BUNDLED_ENCHANTMENTS))
+            .and_then(|data| data.with_uniques(BUNDLED_UNIQUES, BUNDLED_CARDS))
+            .map(|data| data.with_bundled(CSV_FILES))
+            .expect("bundled game data is valid")
+    }
+
+    /// Game data from `itemclasses.csv`, `baseitems.csv`, `mods.csv`,
+    /// `prophecies.csv`, `enchantments.csv`, `uniques.csv` and
+    /// `divination_cards.csv` in `dir`.
+    /// Files missing from the directory fall back to the bundled ones, listed
+    /// by `bundled`, but the directory must hold at least one of them, and
+    /// item classes and base items only come together since bases refer to
+    /// classes by id.
+    /// A directory holding a RePoE `base_items.json` is imported with `from_repoe_dir` instead.
+    pub fn from_dir(dir: &Path) -> Result<Self, DataError> {
+        fs::read_dir(dir).map_err(|error| DataError::Io {
+            path: dir.to_path_buf(),
+            error,
+        })?;
+        if dir.join(REPOE_BASES_FILE).exists() {
+            return PoeData::from_repoe_dir(dir);
+        }
+        let missing = |files: &[&str]| DataError::Missing {
+            dir: dir.to_path_buf(),
+            files: files.iter().map(|f| f.to_string()).collect(),
+        };
+        if !CSV_FILES.iter().any(|file| dir.join(file).exists()) {
+            return Err(missing(CSV_FILES));
+        }
+        match (
+            dir.join(CLASSES_FILE).exists(),
+            dir.join(BASES_FILE).exists(),
+        ) {
+            (true, false) => return Err(missing(&[BASES_FILE])),
+            (false, true) => return Err(missing(&[CLASSES_FILE])),
+            _ => {}
+        }
+        PoeData::from_csv(
+            &read_or_bundled(dir, CLASSES_FILE, BUNDLED_CLASSES)?,
+            &read_or_bundled(dir, BASES_FILE, BUNDLED_BASES)?,
+            &read_or_bundled(dir, MODS_FILE, BUNDLED_MODS)?,
+        )?
+        .with_bundled(&missing_files(dir, &[CLASSES_FILE, BASES_FILE, MODS_FILE]))
+        .with_dir_catalogs(dir)
+    }
+
+    /// Loads from `dir` when one is configured, otherwise uses the bundled data.
+    /// A directory that fails to load also falls back, returning the error with it.
+    pub fn load(dir: Option<&Path>) -> (Self, Option<DataError>) {
+        match dir.map(PoeData::from_dir) {
+            Some(Ok(poe_data)) => (poe_data, None),
+            Some(Err(error)) => (PoeData::new(), Some(error)),
+            None => (PoeData::new(), None),
+        }
+    }
+
+    pub fn from_csv(classes: &str, bases: &str, mods: &str) -> Result<Self, DataError> {
+        let mut bases: Vec<BaseItem> = parse_records(BASES_FILE, bases)?;
+        for (i, base) in bases.iter_mut().enumerate() {
+            base.id = i as i32;
+        }
+        let mut mods: Vec<Mod> = parse_records(MODS_FILE, mods)?;
+        for (i, m) in mods.iter_mut().enumerate() {
+            m.id = i.to_string();
+        }
+        let classes: Vec<ItemClass> = parse_records(CLASSES_FILE, classes)?;
+        let gems = bases
+            .iter()
+            .filter_map(|base| {
+                let class = classes.iter().find(|c| Some(c.id) == base.class)?;
+                if class.category.as_deref() != Some("Gems") {
+                    return None;
+                }
+                Some(Gem {
+                    id: base.id.to_string(),
+                    name: base.name.clone(),
+                    support: class.name.starts_with("Support"),
+                    tags: vec![],
+                })
+            })
+            .collect();
+        Ok(PoeData {
+            classes,
+            bases,
+            mods,
+            gems,
+            ..Default::default()
+        }
+        .reindexed())
+    }
+
+    /// Adds the prophecy and enchantment names, given as CSV with a `name` column.
+    /// They are taken to be every name the game has.
+    pub fn with_catalogs(
+        mut self,
+        prophecies: &str,
+        enchantments: &str,
+    ) -> Result<Self, DataError> {
+        self.bundled
+            .retain(|file| *file != PROPHECIES_FILE && *file != ENCHANTMENTS_FILE);
+        self.prophecies = parse_records(PROPHECIES_FILE, prophecies)?;
+        for (i, prophecy) in self.prophecies.iter_mut().enumerate() {
+            prophecy.id = i as i32;
+        }
+        self.enchantments = parse_records(ENCHANTMENTS_FILE, enchantments)?;
+        for (i, enchantment) in self.enchantments.iter_mut().enumerate() {
+            enchantment.id = i as i32;
+        }
+        Ok(self.reindexed())
+    }
+
+    /// Adds the unique items, as CSV with `name`, `base_type` and `drop_disabled`
+    /// columns, and the divination cards. Every `Divination Card` base becomes
+    /// a card, with the stack size and reward from the `name`, `stack_size`,
+    /// `reward` CSV where it lists them.
+    pub fn with_uniques(mut self, uniques: &str, cards: &str) -> Result<Self, DataError> {
+        self.bundled
+            .retain(|file| *file != UNIQUES_FILE && *file != CARDS_FILE);
+        let rows: Vec<UniqueRow> = parse_records(UNIQUES_FILE, uniques)?;
+        self.uniques = rows
+            .into_iter()
+            .enumerate()
+            .map(|(i, row)| UniqueItem {
+                id: i as i32,
+                base: self.base_by_name(&row.base_type).map(|b| b.id),
+                name: row.name,
+                drop_disabled: row.drop_disabled,
+            })
+            .collect();
+        let rows: Vec<DivinationCard> = parse_records(CARDS_FILE, cards)?;
+        self.divination_cards = self
+            .bases
+            .iter()
+            .filter(|b| self.class_of(b).map(|c| c.name.as_str()) == Some("Divination Card"))
+            .map(|b| {
+                let row = rows.iter().find(|r| r.name == b.name);
+                DivinationCard {
+                    id: b.id,
+                    name: b.name.clone(),
+                    stack_size: row.and_then(|r| r.stack_size),
+                    reward: row.and_then(|r| r.reward.clone()),
+                }
+            })
+            .collect();
+        Ok(self.reindexed())
+    }
+
+    fn with_dir_catalogs(self, dir: &Path) -> Result<Self, DataError> {
+        let bundled = missing_files(
+            dir,
+            &[PROPHECIES_FILE, ENCHANTMENTS_FILE, UNIQUES_FILE, CARDS_FILE],
+        );
+        Ok(self
+            .with_catalogs(
+                &read_or_bundled(dir, PROPHECIES_FILE, BUNDLED_PROPHECIES)?,
+                &read_or_bundled(dir, ENCHANTMENTS_FILE, BUNDLED_ENCHANTMENTS)?,
+            )?
+            .with_uniques(
+                &read_or_bundled(dir, UNIQUES_FILE, BUNDLED_UNIQUES)?,
+                &read_or_bundled(dir, CARDS_FILE, BUNDLED_CARDS)?,
+            )?
+            .with_bundled(&bundled))
+    }
+
+    fn with_bundled(mut self, files: &[&'static str]) -> Self {
+        self.bundled.extend_from_slice(files);
+        self
+    }
+
+    /// Imports the RePoE exports `item_classes.json`, `base_items.json` and
+    /// `mods.json` from `dir`, plus `gems.json` when it is there.
+    /// Prophecies, enchantments, uniques and divination cards come from the
+    /// CSV files, as with `from_dir`.
+    pub fn from_repoe_dir(dir: &Path) -> Result<Self, DataError> {
+        let read = |file: &str| {
+            let path = dir.join(file);
+            fs::read_to_string(&path).map_err(|error| DataError::Io { path, error })
+        };
+        let gems = match read(REPOE_GEMS_FILE) {
+            Ok(gems) => gems,
+            Err(DataError::Io { error, .. }) if error.kind() == io::ErrorKind::NotFound => {
+                String::from("{}")
+            }
+            Err(error) => return Err(error),
         };
+        PoeData::from_repoe(
+            &read(REPOE_CLASSES_FILE)?,
+            &read(REPOE_BASES_FILE)?,
+            &read(REPOE_MODS_FILE)?,
+            &gems,
+        )?
+        .with_dir_catalogs(dir)
+    }
 
-        let classes = include_str!("test_filters/itemclasses.csv");
-        let bases = include_str!("test_filters/baseitems.csv");
-        let mods = include_str!("test_filters/mods.csv");
+    pub fn from_repoe(
+        classes: &str,
+        bases: &str,
+        mods: &str,
+        gems: &str,
+    ) -> Result<Self, DataError> {
+        let repoe_classes: BTreeMap<String, RepoeClass> = parse_json(REPOE_CLASSES_FILE, classes)?;
+        let repoe_bases: BTreeMap<String, RepoeBase> = parse_json(REPOE_BASES_FILE, bases)?;
+        let repoe_mods: BTreeMap<String, RepoeMod> = parse_json(REPOE_MODS_FILE, mods)?;
+        let repoe_gems: BTreeMap<String, RepoeGem> = parse_json(REPOE_GEMS_FILE, gems)?;
 
-        let mut class = csv::Reader::from_reader(classes.as_bytes());
-        let mut base = csv::Reader::from_reader(bases.as_bytes());
-        let mut m = csv::Reader::from_reader(mods.as_bytes());
+        let class_ids = repoe_classes
+            .keys()
+            .enumerate()
+            .map(|(i, key)| (key.as_str(), i as i32))
+            .collect::<BTreeMap<_, _>>();
+        let classes = repoe_classes
+            .iter()
+            .map(|(key, class)| ItemClass {
+                id: class_ids[key.as_str()],
+                name: class.name.clone(),
+                category: class.category.clone(),
+            })
+            .collect();
+        let bases = repoe_bases
+            .values()
+            .filter(|base| base.release_state.as_deref() != Some("unreleased"))
+            .enumerate()
+            .map(|(i, base)| BaseItem {
+                id: i as i32,
+                name: base.name.clone(),
+                class: class_ids.get(base.item_class.as_str()).copied(),
+                domain: domain_id(&base.domain),
+                drop_level: Some(base.drop_level),
+                width: base.inventory_width,
+                height: base.inventory_height,
+                tags: base.tags.clone(),
+                implicits: base.implicits.clone(),
+            })
+            .collect();
 
-        for result in class.deserialize() {
-            let record: Record = result.unwrap();
-            poe_data.classes.push(record);
+        let mut tiers = BTreeMap::<_, Vec<(i32, &str)>>::new();
+        for (id, m) in repoe_mods.iter() {
+            tiers
+                .entry((
+                    m.group.as_str(),
+                    m.domain.as_str(),
+                    m.generation_type.as_str(),
+                ))
+                .or_default()
+                .push((m.required_level, id));
+        }
+        for group in tiers.values_mut() {
+            group.sort_by(|a, b| b.cmp(a));
         }
-        for result in base.deserialize() {
-            let record: Record = result.unwrap();
-            poe_data.bases.push(record);
+        let mods = repoe_mods
+            .iter()
+            .map(|(id, m)| Mod {
+                id: id.clone(),
+                name: m.name.clone(),
+                domain: domain_id(&m.domain),
+                affix: match m.generation_type.as_str() {
+                    "prefix" => Some(Affix::Prefix),
+                    "suffix" => Some(Affix::Suffix),
+                    _ => None,
+                },
+                generation_type: Some(m.generation_type.clone()),
+                tier: tiers[&(
+                    m.group.as_str(),
+                    m.domain.as_str(),
+                    m.generation_type.as_str(),
+                )]
+                    .iter()
+                    .position(|(_, other)| other == id)
+                    .map(|i| i as i32 + 1),
+                required_level: Some(m.required_level),
+                stats: m.stats.clone(),
+                spawn_tags: m
+                    .spawn_weights
+                    .iter()
+                    .filter(|w| w.weight > 0)
+                    .map(|w| w.tag.clone())
+                    .collect(),
+            })
+            .collect();
+        let gems = repoe_gems
+            .iter()
+            .filter_map(|(id, gem)| Some((id, gem, gem.base_item.as_ref()?)))
+            .filter(|(_, _, base)| base.release_state.as_deref() != Some("unreleased"))
+            .map(|(id, gem, base)| Gem {
+                id: id.clone(),
+                name: base.display_name.clone(),
+                support: gem.is_support,
+                tags: gem.tags.clone(),
+            })
+            .collect();
+
+        Ok(PoeData {
+            classes,
+            bases,
+            mods,
+            gems,
+            ..Default::default()
         }
-        for result in m.deserialize() {
-            let record: Record = result.unwrap();
-            poe_data.mods.push(record);
+        .reindexed())
+    }
+
+    /// Rebuilds the name indexes after a loader fills the tables.
+    fn reindex(&mut self) {
+        self.index = DataIndex {
+            classes: NameIndex::new(&self.classes),
+            bases: NameIndex::new(&self.bases),
+            mods: NameIndex::new(&self.mods),
+            gems: NameIndex::new(&self.gems),
+            prophecies: NameIndex::new(&self.prophecies),
+            enchantments: NameIndex::new(&self.enchantments),
+            uniques: NameIndex::new(&self.uniques),
+            divination_cards: NameIndex::new(&self.divination_cards),
+            class_ids: first_positions(self.classes.iter().map(|c| c.id)),
+            mod_ids: first_positions(self.mods.iter().map(|m| m.id.clone())),
+            uniques_by_base: HashMap::new(),
+            cards_by_base: first_positions(self.divination_cards.iter().map(|c| c.id)),
+        };
+        for (i, unique) in self.uniques.iter().enumerate() {
+            if let Some(base) = unique.base {
+                self.index.uniques_by_base.entry(base).or_default().push(i);
+            }
         }
-        poe_data
+    }
+
+    fn reindexed(mut self) -> Self {
+        self.reindex();
+        self
+    }
+
+    /// The data files whose tables hold the bundled data: those missing from
+    /// the data directory, or all of them for `new`.
+    pub fn bundled(&self) -> &[&'static str] {
+        &self.bundled
+    }
+
+    fn is_sample(&self, file: &str) -> bool {
+        SAMPLE_FILES.contains(&file) && self.bundled.contains(&file)
+    }
+
+    pub fn classes(&self) -> Table<'_, ItemClass> {
+        Table::new(&self.classes, &self.index.classes)
+    }
+
+    pub fn bases(&self) -> Table<'_, BaseItem> {
+        Table::new(&self.bases, &self.index.bases)
+    }
+
+    pub fn mods(&self) -> Table<'_, Mod> {
+        Table::new(&self.mods, &self.index.mods)
+    }
+
+    pub fn gems(&self) -> Table<'_, Gem> {
+        Table::new(&self.gems, &self.index.gems)
+    }
+
+    pub fn prophecies(&self) -> Table<'_, Prophecy> {
+        Table::new(&self.prophecies, &self.index.prophecies)
+    }
+
+    pub fn enchantments(&self) -> Table<'_, Enchantment> {
+        Table::new(&self.enchantments, &self.index.enchantments)
+            .sampled(self.is_sample(ENCHANTMENTS_FILE))
+    }
+
+    pub fn uniques(&self) -> Table<'_, UniqueItem> {
+        Table::new(&self.uniques, &self.index.uniques).sampled(self.is_sample(UNIQUES_FILE))
+    }
+
+    pub fn divination_cards(&self) -> Table<'_, DivinationCard> {
+        Table::new(&self.divination_cards, &self.index.divination_cards)
+    }
+
+    pub fn class(&self, id: i32) -> Option<&ItemClass> {
+        self.index.class_ids.get(&id).map(|i| &self.classes[*i])
+    }
+
+    pub fn base(&self, id: i32) -> Option<&BaseItem> {
+        self.bases.get(id as usize).filter(|b| b.id == id)
+    }
+
+    pub fn base_by_name(&self, name: &str) -> Option<&BaseItem> {
+        self.bases().exact(name).into_iter().next()
+    }
+
+    pub fn mod_by_id(&self, id: &str) -> Option<&Mod> {
+        self.index.mod_ids.get(id).map(|i| &self.mods[*i])
+    }
+
+    /// The item class a base item belongs to.
+    pub fn class_of(&self, base: &BaseItem) -> Option<&ItemClass> {
+        base.class.and_then(|id| self.class(id))
+    }
+
+    /// All base items of an item class.
+    pub fn bases_in_class(&self, class: &ItemClass) -> Vec<&BaseItem> {
+        self.bases
+            .iter()
+            .filter(|b| b.class == Some(class.id))
+            .collect()
+    }
+
+    pub fn implicits_of(&self, base: &BaseItem) -> Vec<&Mod> {
+        base.implicits
+            .iter()
+            .filter_map(|id| self.mod_by_id(id))
+            .collect()
+    }
+
+    /// Mods that can roll on a base, going by the base's tags.
+    pub fn mods_for_base(&self, base: &BaseItem) -> Vec<&Mod> {
+        self.mods
+            .iter()
+            .filter(|m| m.spawn_tags.iter().any(|t| base.tags.contains(t)))
+            .collect()
+    }
+
+    /// Bases a mod can roll on, going by the base's tags.
+    pub fn bases_for_mod(&self, m: &Mod) -> Vec<&BaseItem> {
+        self.bases
+            .iter()
+            .filter(|b| m.spawn_tags.iter().any(|t| b.tags.contains(t)))
+            .collect()
+    }
+
+    pub fn base_of_unique(&self, unique: &UniqueItem) -> Option<&BaseItem> {
+        unique.base.and_then(|id| self.base(id))
+    }
+
+    /// Unique items that use a base.
+    pub fn uniques_on(&self, base: &BaseItem) -> Vec<&UniqueItem> {
+        self.index
+            .uniques_by_base
+            .get(&base.id)
+            .into_iter()
+            .flatten()
+            .map(|i| &self.uniques[*i])
+            .collect()
+    }
+
+    /// The divination card a base item is, if any.
+    pub fn card_of(&self, base: &BaseItem) -> Option<&DivinationCard> {
+        self.index
+            .cards_by_base
+            .get(&base.id)
+            .map(|i| &self.divination_cards[*i])
+    }
+
+    /// The gem a base item is, if any.
+    pub fn gem_of(&self, base: &BaseItem) -> Option<&Gem> {
+        self.gems().exact(&base.name).into_iter().next()
+    }
+
+    /// The base types a unique-tier block needs to catch the `valuable`
+    /// uniques, sorted by base name. Names that are not known uniques are skipped.
+    pub fn unique_tier(&self, valuable: &[&str]) -> Vec<UniqueBase<'_>> {
+        let mut tier: Vec<UniqueBase> = vec![];
+        for unique in self
+            .uniques
+            .iter()
+            .filter(|u| valuable.contains(&u.name.as_str()))
+        {
+            let base = match self.base_of_unique(unique) {
+                Some(base) => base,
+                None => continue,
+            };
+            match tier.iter_mut().find(|t| t.base.id == base.id) {
+                Some(entry) => entry.valuable.push(unique),
+                None => tier.push(UniqueBase {
+                    base,
+                    valuable: vec![unique],
+                    others: vec![],
+                }),
+            }
+        }
+        for entry in tier.iter_mut() {
+            entry.others = self
+                .uniques_on(entry.base)
+                .into_iter()
+                .filter(|u| !u.drop_disabled && !valuable.contains(&u.name.as_str()))
+                .collect();
+        }
+        tier.sort_by(|a, b| a.base.name.cmp(&b.base.name));
+        tier
+    }
+}
+
+/// The position of the first item with each key.
+fn first_positions<K: std::hash::Hash + Eq>(keys: impl Iterator<Item = K>) -> HashMap<K, usize> {
+    let mut positions = HashMap::new();
+    for (i, key) in keys.enumerate() {
+        positions.entry(key).or_insert(i);
+    }
+    positions
+}
+
+#[derive(Deserialize)]
+struct UniqueRow {
+    name: String,
+    base_type: String,
+    #[serde(default)]
+    drop_disabled: bool,
+}
+
+/// Reads `file` from `dir`, using the bundled copy when it is not there.
+/// Those of `files` that `dir` does not have.
+fn missing_files(dir: &Path, files: &[&'static str]) -> Vec<&'static str> {
+    files
+        .iter()
+        .copied()
+        .filter(|file| !dir.join(file).exists())
+        .collect()
+}
+
+fn read_or_bundled(dir: &Path, file: &str, bundled: &str) -> Result<String, DataError> {
+    let path = dir.join(file);
+    match fs::read_to_string(&path) {
+        Ok(string) => Ok(string),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(bundled.to_string()),
+        Err(error) => Err(DataError::Io { path, error }),
+    }
+}
+
+fn parse_records<T: serde::de::DeserializeOwned>(
+    file: &str,
+    string: &str,
+) -> Result<Vec<T>, DataError> {
+    csv::Reader::from_reader(string.as_bytes())
+        .deserialize()
+        .collect::<Result<Vec<T>, _>>()
+        .map_err(|error| DataError::Csv {
+            file: file.to_string(),
+            error,
+        })
+}
+
+fn parse_json<T: serde::de::DeserializeOwned>(file: &str, string: &str) -> Result<T, DataError> {
+    serde_json::from_str(string).map_err(|error| DataError::Json {
+        file: file.to_string(),
+        error,
+    })
+}
+
+#[derive(Deserialize)]
+struct RepoeClass {
+    name: String,
+    category: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RepoeBase {
+    name: String,
+    item_class: String,
+    domain: String,
+    drop_level: i32,
+    inventory_width: Option<i32>,
+    inventory_height: Option<i32>,
+    release_state: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    implicits: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct RepoeMod {
+    name: String,
+    domain: String,
+    generation_type: String,
+    #[serde(rename = "type", default)]
+    group: String,
+    #[serde(default)]
+    required_level: i32,
+    #[serde(default)]
+    stats: Vec<ModStat>,
+    #[serde(default)]
+    spawn_weights: Vec<RepoeWeight>,
+}
+
+#[derive(Deserialize)]
+struct RepoeWeight {
+    tag: String,
+    weight: i32,
+}
+
+#[derive(Deserialize)]
+struct RepoeGem {
+    base_item: Option<RepoeGemBase>,
+    #[serde(default)]
+    is_support: bool,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct RepoeGemBase {
+    display_name: String,
+    release_state: Option<String>,
+}
+
+/// Domain id for the domain names used in the RePoE exports.
+fn domain_id(domain: &str) -> Option<i32> {
+    match domain {
+        "item" => Some(1),
+        "flask" => Some(2),
+        "monster" => Some(3),
+        "chest" => Some(4),
+        "area" => Some(5),
+        "stance" => Some(9),
+        "crafted" => Some(10),
+        "misc" => Some(11),
+        "atlas" => Some(12),
+        "leaguestone" => Some(13),
+        "abyss_jewel" => Some(14),
+        "map_device" => Some(15),
+        "dummy" => Some(16),
+        "delve" => Some(17),
+        "delve_area" => Some(18),
+        "synthesis_a" => Some(19),
+        "synthesis_globals" => Some(20),
+        "synthesis_bonus" => Some(21),
+        _ => None,
+    }
+}
+
+/// Name of a mod domain id as used in the game data.
+pub fn domain_name(domain: i32) -> &'static str {
+    match domain {
+        1 => "Item",
+        2 => "Flask",
+        3 => "Monster",
+        4 => "Strongbox",
+        5 => "Map",
+        9 => "Stance",
+        10 => "Crafted",
+        11 => "Jewel",
+        12 => "Atlas",
+        13 => "Leaguestone",
+        14 => "Abyss Jewel",
+        15 => "Map Device",
+        16 => "Dummy",
+        17 => "Delve",
+        18 => "Delve Area",
+        19 => "Synthesis",
+        20 => "Synthesis Globals",
+        21 => "Synthesis Bonus",
+        _ => "Unknown",
+    }
+}
+
+/// Name of an alert sound id given to `PlayAlertSound`, either one of the
+/// numbered sounds 1-16 or a named `Sh` sound.
+pub fn sound_name(id: &str) -> Option<String> {
+    match id {
+        "None" => Some(String::from("No sound")),
+        "ShAlchemy" => Some(String::from("Orb of Alchemy")),
+        "ShBlessed" => Some(String::from("Blessed Orb")),
+        "ShChaos" => Some(String::from("Chaos Orb")),
+        "ShDivine" => Some(String::from("Divine Orb")),
+        "ShExalted" => Some(String::from("Exalted Orb")),
+        "ShFusing" => Some(String::from("Orb of Fusing")),
+        "ShGeneral" => Some(String::from("General")),
+        "ShMirror" => Some(String::from("Mirror of Kalandra")),
+        "ShRegal" => Some(String::from("Regal Orb")),
+        "ShVaal" => Some(String::from("Vaal Orb")),
+        _ => match id.parse::<u8>() {
+            Ok(n) if (1..=16).contains(&n) => Some(format!("Alert Sound {}", n)),
+            _ => None,
+        },
     }
 }
@@ -0,0 +1,144 @@
+use crate::data_parsing::{PoeData, QUALITY_TYPES};
+use crate::hover;
+use crate::imports::Imported;
+use crate::lookup::{Named, Table};
+use crate::mode_parsing::{Filter, Token, TokenAndSpan, ValueAndSpan};
+use lsp_types::*;
+
+/// Warnings for names in `BaseType`, `Class`, `HasExplicitMod`, `Prophecy`,
+/// `HasEnchantment` and `GemQualityType` lines that match nothing in the game data.
+/// Catalogs that are only a sample, like the bundled enchantments, are not checked.
+pub fn unknown_names(filter: &Filter, string: &str, poe_data: &PoeData) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    for line in filter.vec.iter().flat_map(|block| block.keywords.iter()) {
+        let exact = line.operator() == Some("==");
+        let unknown = |text: &str| match line.token {
+            Token::BaseType => unknown_in(poe_data.bases(), text, exact, "base type"),
+            Token::Class => unknown_in(poe_data.classes(), text, exact, "item class"),
+            Token::HasExplicitMod => unknown_in(poe_data.mods(), text, exact, "mod"),
+            Token::Prophecy => unknown_in(poe_data.prophecies(), text, exact, "prophecy"),
+            Token::HasEnchantment => {
+                unknown_in(poe_data.enchantments(), text, exact, "enchantment")
+            }
+            Token::GemQualityType if !QUALITY_TYPES.contains(&text) => {
+                Some(format!("Unknown quality type \"{}\"", text))
+            }
+            _ => None,
+        };
+        for value in values(line) {
+            if let Some(message) = unknown(value.value.trim_matches('"')) {
+                diagnostics.push(Diagnostic {
+                    range: Range::new(
+                        hover::position_in_string(value.span.start, string),
+                        hover::position_in_string(value.span.end, string),
+                    ),
+                    severity: Some(DiagnosticSeverity::Warning),
+                    source: Some(String::from("poe-filter")),
+                    message,
+                    ..Diagnostic::default()
+                });
+            }
+        }
+    }
+    diagnostics
+}
+
+/// Errors for variables and mixins used without a definition here or in an imported
+/// file, and warnings for ones defined twice.
+pub fn variables(filter: &Filter, string: &str, imported: &[Imported]) -> Vec<Diagnostic> {
+    let diagnostic = |span: &std::ops::Range<usize>, severity, message| Diagnostic {
+        range: Range::new(
+            hover::position_in_string(span.start, string),
+            hover::position_in_string(span.end, string),
+        ),
+        severity: Some(severity),
+        source: Some(String::from("poe-filter")),
+        message,
+        ..Diagnostic::default()
+    };
+    let mut diagnostics = vec![];
+    for (i, definition) in filter.variables.iter().enumerate() {
+        let first = filter.variables[..i]
+            .iter()
+            .find(|d| d.token == definition.token);
+        if let (Some(first), Token::Variable(name)) = (first, &definition.token) {
+            let line = hover::position_in_string(first.span.start, string).line + 1;
+            diagnostics.push(diagnostic(
+                &definition.span,
+                DiagnosticSeverity::Warning,
+                format!("{} is already defined on line {}", name, line),
+            ));
+        }
+    }
+    for (i, mixin) in filter.mixins.iter().enumerate() {
+        if let Some(first) = filter.mixins[..i].iter().find(|m| m.name == mixin.name) {
+            let line = hover::position_in_string(first.span.start, string).line + 1;
+            diagnostics.push(diagnostic(
+                &mixin.span,
+                DiagnosticSeverity::Warning,
+                format!("{} is already defined on line {}", mixin.name, line),
+            ));
+        }
+    }
+    for line in filter.lines() {
+        if let Token::Variable(name) = &line.token {
+            let defined = filter.mixin(name).is_some()
+                || imported.iter().any(|i| i.filter.mixin(name).is_some());
+            if !defined {
+                diagnostics.push(diagnostic(
+                    &line.span,
+                    DiagnosticSeverity::Error,
+                    format!("Undefined mixin {}", name),
+                ));
+            }
+        }
+    }
+    let uses = filter
+        .lines()
+        .chain(filter.variables.iter())
+        .flat_map(|line| line.value.iter());
+    for value in uses {
+        if let Token::Variable(name) = &value.token {
+            let defined = filter.definition(name).is_some()
+                || imported.iter().any(|i| i.filter.definition(name).is_some());
+            if !defined {
+                diagnostics.push(diagnostic(
+                    &value.span,
+                    DiagnosticSeverity::Error,
+                    format!("Undefined variable {}", name),
+                ));
+            }
+        }
+    }
+    diagnostics
+}
+
+/// The values of a line the game compares, leaving out the operator and variables.
+fn values(line: &TokenAndSpan) -> impl Iterator<Item = &ValueAndSpan> {
+    line.value
+        .iter()
+        .filter(|v| !matches!(v.token, Token::Operator(_) | Token::Variable(_)))
+}
+
+/// A message naming `text` and the closest known names, when nothing matches it.
+fn unknown_in<T: Named>(table: Table<T>, text: &str, exact: bool, what: &str) -> Option<String> {
+    if table.is_sample() || !table.matching(text, exact).is_empty() {
+        return None;
+    }
+    let suggestions = table
+        .fuzzy(text)
+        .iter()
+        .take(3)
+        .map(|s| format!("\"{}\"", s.item.name()))
+        .collect::<Vec<_>>();
+    if suggestions.is_empty() {
+        Some(format!("Unknown {} \"{}\"", what, text))
+    } else {
+        Some(format!(
+            "Unknown {} \"{}\", did you mean {}?",
+            what,
+            text,
+            suggestions.join(", ")
+        ))
+    }
+}
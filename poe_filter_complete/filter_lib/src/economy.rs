@@ -0,0 +1,262 @@
+use crate::data_parsing::DataError;
+use serde::Deserialize;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// The kind of item a price snapshot covers, taken from the file name.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Category {
+    Currency,
+    Fragment,
+    DivinationCard,
+    Unique,
+    Scarab,
+    Oil,
+    Fossil,
+    Essence,
+    Other,
+}
+
+impl Category {
+    /// Category for a snapshot file name like `Currency.json` or `unique_armour.csv`.
+    pub fn from_file_name(name: &str) -> Category {
+        let name = name
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_lowercase();
+        let prefixes = [
+            ("currency", Category::Currency),
+            ("fragment", Category::Fragment),
+            ("divination", Category::DivinationCard),
+            ("unique", Category::Unique),
+            ("scarab", Category::Scarab),
+            ("oil", Category::Oil),
+            ("fossil", Category::Fossil),
+            ("essence", Category::Essence),
+        ];
+        prefixes
+            .iter()
+            .find(|(prefix, _)| name.starts_with(prefix))
+            .map(|(_, category)| *category)
+            .unwrap_or(Category::Other)
+    }
+}
+
+/// How much a price can be trusted, going by how many listings it is based on.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Confidence {
+    Low,
+    Medium,
+    High,
+}
+
+impl Confidence {
+    pub fn from_count(count: u32) -> Confidence {
+        match count {
+            0..=9 => Confidence::Low,
+            10..=49 => Confidence::Medium,
+            _ => Confidence::High,
+        }
+    }
+}
+
+impl fmt::Display for Confidence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Confidence::Low => write!(f, "low confidence"),
+            Confidence::Medium => write!(f, "medium confidence"),
+            Confidence::High => write!(f, "high confidence"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Price {
+    pub name: String,
+    /// The item's base type, the same as `name` for everything but uniques.
+    pub base_type: String,
+    pub category: Category,
+    pub chaos_value: f64,
+    /// Number of listings the value is based on.
+    pub count: u32,
+    pub confidence: Confidence,
+    /// Price variants like 6-linked uniques, which a filter cannot tell apart by name.
+    pub links: Option<u32>,
+}
+
+/// Prices from local snapshot files, so tiering works offline.
+#[derive(Clone, Debug, Default)]
+pub struct Prices {
+    pub prices: Vec<Price>,
+}
+
+impl Prices {
+    /// Every `.json` and `.csv` snapshot in `dir`, with the category taken from the file name.
+    pub fn from_dir(dir: &Path) -> Result<Self, DataError> {
+        let entries = fs::read_dir(dir).map_err(|error| DataError::Io {
+            path: dir.to_path_buf(),
+            error,
+        })?;
+        let mut paths = entries
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .collect::<Vec<_>>();
+        paths.sort();
+
+        let mut prices = Prices::default();
+        for path in paths.iter() {
+            let file = path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let category = Category::from_file_name(&file);
+            let parse = match path.extension().and_then(|e| e.to_str()) {
+                Some("json") => Prices::from_json,
+                Some("csv") => Prices::from_csv,
+                _ => continue,
+            };
+            let string = fs::read_to_string(path).map_err(|error| DataError::Io {
+                path: path.clone(),
+                error,
+            })?;
+            prices
+                .prices
+                .extend(parse(&file, category, &string)?.prices);
+        }
+        Ok(prices)
+    }
+
+    /// A poe.ninja `currencyoverview` or `itemoverview` response.
+    pub fn from_json(file: &str, category: Category, string: &str) -> Result<Self, DataError> {
+        let overview: NinjaOverview =
+            serde_json::from_str(string).map_err(|error| DataError::Json {
+                file: file.to_string(),
+                error,
+            })?;
+        let prices = overview
+            .lines
+            .into_iter()
+            .filter_map(|line| {
+                let name = line.currency_type_name.or(line.name)?;
+                let chaos_value = line.chaos_value.or(line.chaos_equivalent)?;
+                let receive = line.receive.map(|r| r.count);
+                let count = line.count.or(receive).unwrap_or_default();
+                Some(Price {
+                    base_type: line.base_type.unwrap_or_else(|| name.clone()),
+                    name,
+                    category,
+                    chaos_value,
+                    count,
+                    confidence: Confidence::from_count(count),
+                    links: line.links.filter(|l| *l > 0),
+                })
+            })
+            .collect();
+        Ok(Prices { prices })
+    }
+
+    /// CSV with `name`, `chaos_value` and optional `base_type`, `count` and
+    /// `category` columns; the category column overrides the file's.
+    pub fn from_csv(file: &str, category: Category, string: &str) -> Result<Self, DataError> {
+        let prices = csv::Reader::from_reader(string.as_bytes())
+            .deserialize()
+            .collect::<Result<Vec<CsvPrice>, _>>()
+            .map_err(|error| DataError::Csv {
+                file: file.to_string(),
+                error,
+            })?
+            .into_iter()
+            .map(|row| {
+                let count = row.count.unwrap_or_default();
+                let base_type = row.base_type.clone().unwrap_or_else(|| row.name.clone());
+                Price {
+                    base_type,
+                    name: row.name,
+                    category: row
+                        .category
+                        .map(|c| Category::from_file_name(&c))
+                        .unwrap_or(category),
+                    chaos_value: row.chaos_value,
+                    count,
+                    confidence: Confidence::from_count(count),
+                    links: None,
+                }
+            })
+            .collect();
+        Ok(Prices { prices })
+    }
+
+    /// The price of an item by name, skipping linked variants and preferring
+    /// the most listed entry.
+    pub fn by_name(&self, name: &str) -> Option<&Price> {
+        self.prices
+            .iter()
+            .filter(|p| p.name == name && p.links.is_none())
+            .max_by_key(|p| p.count)
+    }
+
+    /// The price of a base type itself, like a currency item or divination card.
+    /// Unique prices are left out; see `uniques_on`.
+    pub fn by_base(&self, base_type: &str) -> Option<&Price> {
+        self.prices
+            .iter()
+            .filter(|p| p.category != Category::Unique)
+            .filter(|p| p.base_type == base_type && p.links.is_none())
+            .max_by_key(|p| p.count)
+    }
+
+    /// Unlinked unique prices on a base type, most valuable first.
+    pub fn uniques_on(&self, base_type: &str) -> Vec<&Price> {
+        let mut out = self
+            .prices
+            .iter()
+            .filter(|p| p.category == Category::Unique)
+            .filter(|p| p.base_type == base_type && p.links.is_none())
+            .collect::<Vec<_>>();
+        out.sort_by(|a, b| b.chaos_value.total_cmp(&a.chaos_value));
+        out
+    }
+}
+
+/// Chaos value and how sure the snapshot is of it, as shown in hovers, like
+/// `12.5c (high confidence)`.
+pub fn format_chaos(price: &Price) -> String {
+    format!(
+        "{}c ({})",
+        (price.chaos_value * 10.0).round() / 10.0,
+        price.confidence
+    )
+}
+
+#[derive(Deserialize)]
+struct NinjaOverview {
+    lines: Vec<NinjaLine>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NinjaLine {
+    currency_type_name: Option<String>,
+    name: Option<String>,
+    base_type: Option<String>,
+    chaos_value: Option<f64>,
+    chaos_equivalent: Option<f64>,
+    count: Option<u32>,
+    receive: Option<NinjaReceive>,
+    links: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct NinjaReceive {
+    count: u32,
+}
+
+#[derive(Deserialize)]
+struct CsvPrice {
+    name: String,
+    base_type: Option<String>,
+    category: Option<String>,
+    chaos_value: f64,
+    count: Option<u32>,
+}
@@ -1,56 +1,257 @@
-use crate::mode_parsing::Filter;
+use crate::data_parsing::{domain_name, sound_name, PoeData};
+use crate::economy::format_chaos;
+use crate::imports::Imported;
+use crate::mode_parsing::{Filter, Token, TokenAndSpan, ValueAndSpan};
+use crate::variables;
 // use crate::mode_parsing::{Filter, Token};
 // use log::info;
 // use logos::{Lexer, Logos};
 use lsp_types::*;
-use std::fs;
-use std::path::PathBuf;
 
-pub fn hover_keyword(params: HoverParams, filter: &Filter) -> Vec<MarkedString> {
+/// Hover text at the cursor. `text` is the document as the editor has it,
+/// which `filter` was parsed from; the copy on disk may be older.
+pub fn hover_keyword(
+    params: HoverParams,
+    filter: &Filter,
+    text: &str,
+    imported: &[Imported],
+    poe_data: &PoeData,
+) -> Vec<MarkedString> {
     let mut hovers = vec![];
-    if let Some((string, _path)) = get_string_and_path(&params) {
-        // info!("successfully got path and string");
-        let byte = byte_pos_in_string(
-            params.text_document_position_params.position.line as usize,
-            params.text_document_position_params.position.character as usize,
-            string,
-        );
-        if let Some(token) = filter.search_bytes(byte) {
-            if let Some(keyword) = token.keyword_type() {
-                let hover_keyword_text = format!("Type: {:?}\n\n{}", keyword, token.description());
-                hovers.push(MarkedString::String(hover_keyword_text))
-            }
+    let byte = byte_pos_in_string(
+        params.text_document_position_params.position.line as usize,
+        params.text_document_position_params.position.character as usize,
+        text.to_string(),
+    );
+    if let Some((name, _)) = filter.variable_at(byte) {
+        hovers.push(MarkedString::String(variables::hover(
+            filter, name, text, imported,
+        )));
+        return hovers;
+    }
+    if let Some((line, value)) = filter.search_value(byte) {
+        if let Some(text) = hover_value(line, value, poe_data) {
+            hovers.push(MarkedString::String(text));
+            return hovers;
+        }
+    }
+    if let Some(token) = filter.search_bytes(byte) {
+        if let Some(keyword) = token.keyword_type() {
+            let hover_keyword_text = format!("Type: {:?}\n\n{}", keyword, token.description());
+            hovers.push(MarkedString::String(hover_keyword_text))
         }
     }
     hovers
 }
 
-fn get_string_and_path(params: &HoverParams) -> Option<(String, PathBuf)> {
-    if let Ok(path) = params
-        .text_document_position_params
-        .text_document
-        .uri
-        .to_file_path()
-    {
-        if let Ok(string) = fs::read_to_string(&path) {
-            Some((string, path))
-        } else {
-            None
+const MAX_LISTED: usize = 20;
+
+/// Describes what a value stands for in the game, like the bases a
+/// `BaseType` string matches. `None` when there is nothing specific to say.
+pub fn hover_value(
+    line: &TokenAndSpan,
+    value: &ValueAndSpan,
+    poe_data: &PoeData,
+) -> Option<String> {
+    let text = value.value.trim_matches('"');
+    let exact = line.operator() == Some("==");
+    match line.token {
+        Token::BaseType => {
+            let matched = poe_data.bases().matching(text, exact);
+            let bases = matched
+                .iter()
+                .map(|b| {
+                    let class = poe_data
+                        .class_of(b)
+                        .map(|c| c.name.as_str())
+                        .unwrap_or_default();
+                    let line = format!(
+                        "{} - {}, DropLevel {}",
+                        b.name,
+                        class,
+                        b.drop_level.unwrap_or_default()
+                    );
+                    match poe_data.prices.by_base(&b.name) {
+                        Some(price) => format!("{}, {}", line, format_chaos(price)),
+                        None => line,
+                    }
+                })
+                .collect::<Vec<_>>();
+            let mut out = format!("# BaseType \"{}\"\n\n{}", text, list(&bases, "base types"));
+            if bases.is_empty() {
+                let suggestions = poe_data
+                    .bases()
+                    .fuzzy(text)
+                    .iter()
+                    .take(3)
+                    .map(|s| format!("\"{}\"", s.item.name))
+                    .collect::<Vec<_>>();
+                if !suggestions.is_empty() {
+                    out.push_str(&format!("\n\nDid you mean {}?", suggestions.join(", ")));
+                }
+            }
+            let uniques = matched
+                .iter()
+                .flat_map(|b| poe_data.uniques_on(b))
+                .map(|u| {
+                    let base = poe_data
+                        .base_of_unique(u)
+                        .map(|b| b.name.as_str())
+                        .unwrap_or_default();
+                    let line = if u.drop_disabled {
+                        format!("{} - {} (drop disabled)", u.name, base)
+                    } else {
+                        format!("{} - {}", u.name, base)
+                    };
+                    match poe_data.prices.by_name(&u.name) {
+                        Some(price) => format!("{}, {}", line, format_chaos(price)),
+                        None => line,
+                    }
+                })
+                .collect::<Vec<_>>();
+            if !uniques.is_empty() {
+                let what = if poe_data.uniques().is_sample() {
+                    "uniques (sample)"
+                } else {
+                    "uniques"
+                };
+                out.push_str(&format!("\n\n{}", list(&uniques, what)));
+            }
+            if let [base] = matched.as_slice() {
+                if let Some(card) = poe_data.card_of(base) {
+                    if let (Some(stack), Some(reward)) = (card.stack_size, &card.reward) {
+                        out.push_str(&format!("\n\nDivination card: {} for {}", stack, reward));
+                    }
+                }
+                if let Some(gem) = poe_data.gem_of(base) {
+                    let kind = if gem.support {
+                        "Support gem"
+                    } else {
+                        "Skill gem"
+                    };
+                    match gem.tags.as_slice() {
+                        [] => out.push_str(&format!("\n\n{}", kind)),
+                        tags => out.push_str(&format!("\n\n{}: {}", kind, tags.join(", "))),
+                    }
+                }
+            }
+            Some(out)
+        }
+        Token::Class => {
+            let classes = poe_data
+                .classes()
+                .matching(text, exact)
+                .into_iter()
+                .map(|c| c.name.clone())
+                .collect::<Vec<_>>();
+            Some(format!(
+                "# Class \"{}\"\n\n{}",
+                text,
+                list(&classes, "item classes")
+            ))
         }
-    } else {
-        None
+        Token::Prophecy => {
+            let prophecies = poe_data
+                .prophecies()
+                .matching(text, exact)
+                .into_iter()
+                .map(|p| p.name.clone())
+                .collect::<Vec<_>>();
+            Some(format!(
+                "# Prophecy \"{}\"\n\n{}",
+                text,
+                list(&prophecies, "prophecies")
+            ))
+        }
+        Token::HasEnchantment => {
+            let enchantments = poe_data
+                .enchantments()
+                .matching(text, exact)
+                .into_iter()
+                .map(|e| e.name.clone())
+                .collect::<Vec<_>>();
+            Some(format!(
+                "# HasEnchantment \"{}\"\n\n{}",
+                text,
+                list(&enchantments, "enchantments")
+            ))
+        }
+        Token::GemQualityType => Some(format!(
+            "# GemQualityType {}\n\n{}",
+            text,
+            quality_type(text)?
+        )),
+        Token::HasExplicitMod => {
+            let mut domains = poe_data
+                .mods()
+                .matching(text, exact)
+                .into_iter()
+                .filter_map(|m| m.domain)
+                .map(domain_name)
+                .collect::<Vec<_>>();
+            domains.sort_unstable();
+            domains.dedup();
+            if domains.is_empty() {
+                Some(format!("# HasExplicitMod \"{}\"\n\nUnknown mod", text))
+            } else {
+                Some(format!(
+                    "# HasExplicitMod \"{}\"\n\nDomain: {}",
+                    text,
+                    domains.join(", ")
+                ))
+            }
+        }
+        Token::SetTextColor | Token::SetBorderColor | Token::SetBackgroundColor => {
+            let [r, g, b, a] = line.color()?;
+            Some(format!(
+                "# Color\n\nRGBA({}, {}, {}, {})  \nHex: #{:02X}{:02X}{:02X}{:02X}",
+                r, g, b, a, r, g, b, a
+            ))
+        }
+        Token::PlayAlertSound | Token::PlayAlertSoundPositional => {
+            if line.strings().first() == Some(&text) {
+                Some(format!("# Sound {}\n\n{}", text, sound_name(text)?))
+            } else {
+                Some(format!("# Volume\n\n{} of 300", text))
+            }
+        }
+        _ => None,
     }
 }
 
+/// What an alternate quality type does to a gem.
+fn quality_type(name: &str) -> Option<&'static str> {
+    match name {
+        "Superior" => Some("The gem's regular quality bonus"),
+        "Anomalous" | "Divergent" | "Phantasmal" => {
+            Some("An alternate quality bonus, obtained with a Lens or from Heist")
+        }
+        _ => None,
+    }
+}
+
+fn list(names: &[String], what: &str) -> String {
+    if names.is_empty() {
+        return format!("Matches no {}", what);
+    }
+    let mut out = format!("Matches {} {}:\n", names.len(), what);
+    for name in names.iter().take(MAX_LISTED) {
+        out.push_str(&format!("\n- {}", name));
+    }
+    if names.len() > MAX_LISTED {
+        out.push_str(&format!("\n- ... and {} more", names.len() - MAX_LISTED));
+    }
+    out
+}
+
 pub fn byte_pos_in_string(line: usize, char: usize, string: String) -> usize {
     let mut byte_pos: usize = 0;
-    for (i, s) in string.lines().enumerate() {
+    for (i, s) in string.split_inclusive('\n').enumerate() {
         if i == line {
             byte_pos += char;
             return byte_pos;
         } else {
             byte_pos += s.len();
-            byte_pos += 2;
         }
     }
     // info!("{}", byte_pos);
@@ -58,6 +259,47 @@ pub fn byte_pos_in_string(line: usize, char: usize, string: String) -> usize {
     byte_pos
 }
 
+/// The line and character of a byte offset, the inverse of `byte_pos_in_string`.
+pub fn position_in_string(byte: usize, string: &str) -> Position {
+    let before = &string[..byte.min(string.len())];
+    let line = before.matches('\n').count();
+    let start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    Position::new(line as u64, (before.len() - start) as u64)
+}
+
+/// Where each line of a text starts, for converting many offsets without
+/// scanning the text each time. Converts like `position_in_string` and
+/// `byte_pos_in_string`.
+pub struct LineStarts {
+    starts: Vec<usize>,
+    len: usize,
+}
+
+impl LineStarts {
+    pub fn new(string: &str) -> Self {
+        let starts = std::iter::once(0)
+            .chain(string.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        LineStarts {
+            starts,
+            len: string.len(),
+        }
+    }
+
+    pub fn position(&self, byte: usize) -> Position {
+        let byte = byte.min(self.len);
+        let line = self.starts.partition_point(|start| *start <= byte) - 1;
+        Position::new(line as u64, (byte - self.starts[line]) as u64)
+    }
+
+    pub fn byte(&self, position: Position) -> usize {
+        match self.starts.get(position.line as usize) {
+            Some(start) => start + position.character as usize,
+            None => self.len,
+        }
+    }
+}
+
 // OLD
 // pub fn hover_keyword(params: HoverParams) -> Vec<MarkedString> {
 // 	let mut hovers = vec![];
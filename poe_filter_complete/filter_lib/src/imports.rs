@@ -0,0 +1,252 @@
+use crate::hover::position_in_string;
+use crate::mode_parsing::{apply_edits, parse, Filter, Token, TokenAndSpan};
+use lsp_types::*;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum ImportError {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    /// An import that leads back to a file importing it; the chain starts
+    /// and ends with that file.
+    Cycle {
+        chain: Vec<PathBuf>,
+    },
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImportError::Io { path, error } => {
+                write!(f, "could not read {}: {}", path.display(), error)
+            }
+            ImportError::Cycle { chain } => {
+                let chain = chain
+                    .iter()
+                    .map(|p| p.display().to_string())
+                    .collect::<Vec<_>>();
+                write!(f, "import cycle: {}", chain.join(" -> "))
+            }
+        }
+    }
+}
+
+impl Error for ImportError {}
+
+/// A filter read from disk, through an `Import` line or from the workspace.
+#[derive(Clone, Debug)]
+pub struct Imported {
+    pub path: PathBuf,
+    pub text: String,
+    pub filter: Filter,
+}
+
+/// The quoted path of an `Import` line, without its quotes.
+pub fn import_path(line: &TokenAndSpan) -> Option<&str> {
+    line.strings().first().copied()
+}
+
+/// Where an import points, relative to the directory of the importing file.
+pub fn resolve(importer: &Path, path: &str) -> PathBuf {
+    importer
+        .parent()
+        .map(|dir| dir.join(path))
+        .unwrap_or_else(|| PathBuf::from(path))
+}
+
+fn key(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Every filter `filter` imports, directly or through other imports, each
+/// once. Imports that cannot be read or lead back around are skipped here;
+/// `problems` reports them.
+pub fn load(path: &Path, filter: &Filter) -> Vec<Imported> {
+    let mut out = Vec::<Imported>::new();
+    let mut queue = filter
+        .imports
+        .iter()
+        .filter_map(|line| Some(resolve(path, import_path(line)?)))
+        .collect::<Vec<_>>();
+    let root = key(path);
+    while let Some(next) = queue.pop() {
+        if key(&next) == root || out.iter().any(|i| key(&i.path) == key(&next)) {
+            continue;
+        }
+        let text = match fs::read_to_string(&next) {
+            Ok(text) => text,
+            Err(_) => continue,
+        };
+        let filter = parse(&text);
+        queue.extend(
+            filter
+                .imports
+                .iter()
+                .filter_map(|line| Some(resolve(&next, import_path(line)?))),
+        );
+        out.push(Imported {
+            path: next,
+            text,
+            filter,
+        });
+    }
+    out
+}
+
+/// Errors for imports of missing files and imports that lead back to the file,
+/// going by the files `load` already read rather than the disk.
+pub fn problems(
+    path: &Path,
+    filter: &Filter,
+    text: &str,
+    imported: &[Imported],
+) -> Vec<Diagnostic> {
+    let files = imported
+        .iter()
+        .map(|i| (key(&i.path), i))
+        .collect::<Vec<_>>();
+    let mut diagnostics = vec![];
+    for line in filter.imports.iter() {
+        let (value, import) = match (line.value.first(), import_path(line)) {
+            (Some(value), Some(import)) => (value, import),
+            _ => continue,
+        };
+        let message = match check(&resolve(path, import), &files, &mut vec![key(path)]) {
+            Ok(_) => continue,
+            Err(error) => error.to_string(),
+        };
+        diagnostics.push(Diagnostic {
+            range: Range::new(
+                position_in_string(value.span.start, text),
+                position_in_string(value.span.end, text),
+            ),
+            severity: Some(DiagnosticSeverity::Error),
+            source: Some(String::from("poe-filter")),
+            message,
+            ..Diagnostic::default()
+        });
+    }
+    diagnostics
+}
+
+/// Follows the imports of the file at `path` through `files`, failing on one
+/// that was not read or that leads back to a file on `stack`.
+fn check(
+    path: &Path,
+    files: &[(PathBuf, &Imported)],
+    stack: &mut Vec<PathBuf>,
+) -> Result<(), ImportError> {
+    let this = key(path);
+    cycle(&this, stack)?;
+    let file = match files.iter().find(|(k, _)| *k == this) {
+        Some((_, file)) => file,
+        None => {
+            return Err(ImportError::Io {
+                path: path.to_path_buf(),
+                error: io::ErrorKind::NotFound.into(),
+            })
+        }
+    };
+    stack.push(this);
+    for import in file.filter.imports.iter().filter_map(import_path) {
+        check(&resolve(&file.path, import), files, stack)?;
+    }
+    stack.pop();
+    Ok(())
+}
+
+fn cycle(this: &Path, stack: &[PathBuf]) -> Result<(), ImportError> {
+    match stack.iter().position(|p| p == this) {
+        Some(at) => {
+            let mut chain = stack[at..].to_vec();
+            chain.push(this.to_path_buf());
+            Err(ImportError::Cycle { chain })
+        }
+        None => Ok(()),
+    }
+}
+
+/// The filter at `path` with every `Import` line replaced by the imported
+/// filter, recursively, so the game can load it as one file. A file imported
+/// more than once is only included the first time.
+pub fn bundle(path: &Path) -> Result<String, ImportError> {
+    bundle_from(path, &mut vec![], &mut vec![])
+}
+
+fn bundle_from(
+    path: &Path,
+    stack: &mut Vec<PathBuf>,
+    included: &mut Vec<PathBuf>,
+) -> Result<String, ImportError> {
+    let this = key(path);
+    cycle(&this, stack)?;
+    if included.contains(&this) {
+        return Ok(String::new());
+    }
+    let text = fs::read_to_string(path).map_err(|error| ImportError::Io {
+        path: path.to_path_buf(),
+        error,
+    })?;
+    let filter = parse(&text);
+    included.push(this.clone());
+    stack.push(this);
+    let mut edits = vec![];
+    for line in filter.imports.iter() {
+        let start = text[..line.span.start].rfind('\n').map_or(0, |i| i + 1);
+        let end = text[line.span.start..]
+            .find('\n')
+            .map_or(text.len(), |i| line.span.start + i + 1);
+        let mut imported = match import_path(line) {
+            Some(import) => bundle_from(&resolve(path, import), stack, included)?,
+            None => String::new(),
+        };
+        if end < text.len() && !imported.is_empty() && !imported.ends_with('\n') {
+            imported.push('\n');
+        }
+        edits.push((start..end, imported));
+    }
+    stack.pop();
+    Ok(apply_edits(&text, edits))
+}
+
+/// Links from each `Import` path to the file it names.
+pub fn document_links(path: &Path, filter: &Filter, text: &str) -> Vec<DocumentLink> {
+    filter
+        .imports
+        .iter()
+        .filter_map(|line| {
+            let value = line
+                .value
+                .iter()
+                .find(|v| matches!(v.token, Token::Quotes(_)))?;
+            let target = Url::from_file_path(resolve(path, import_path(line)?)).ok()?;
+            Some(DocumentLink {
+                range: Range::new(
+                    position_in_string(value.span.start + 1, text),
+                    position_in_string(value.span.end - 1, text),
+                ),
+                target,
+                tooltip: None,
+            })
+        })
+        .collect()
+}
+
+/// The `Import` line at `byte` and the file it names.
+pub fn import_at<'a>(
+    filter: &'a Filter,
+    path: &Path,
+    byte: usize,
+) -> Option<(&'a TokenAndSpan, PathBuf)> {
+    let line = filter.imports.iter().find(|line| {
+        let end = line.value.last().map_or(line.span.end, |v| v.span.end);
+        line.span.start <= byte && end >= byte
+    })?;
+    Some((line, resolve(path, import_path(line)?)))
+}
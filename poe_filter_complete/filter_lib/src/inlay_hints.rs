@@ -0,0 +1,122 @@
+use crate::data_parsing::{sound_name, PoeData};
+use crate::hover::LineStarts;
+use crate::mode_parsing::{Filter, Token, TokenAndSpan};
+use lsp_types::*;
+use serde::{Deserialize, Serialize};
+
+/// `textDocument/inlayHint`, which lsp-types 0.74 predates; the params and
+/// hints below carry only the fields this server uses.
+pub enum InlayHintRequest {}
+
+impl lsp_types::request::Request for InlayHintRequest {
+    type Params = InlayHintParams;
+    type Result = Option<Vec<InlayHint>>;
+    const METHOD: &'static str = "textDocument/inlayHint";
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InlayHintParams {
+    pub text_document: TextDocumentIdentifier,
+    pub range: Range,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InlayHint {
+    pub position: Position,
+    pub label: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub padding_left: Option<bool>,
+}
+
+fn icon_size(size: &str) -> Option<&'static str> {
+    match size {
+        "0" => Some("large"),
+        "1" => Some("medium"),
+        "2" => Some("small"),
+        _ => None,
+    }
+}
+
+/// The hint after a line's values and the byte it goes after, if the line
+/// has something to say.
+fn hint(line: &TokenAndSpan) -> Option<(usize, String)> {
+    let values = line
+        .value
+        .iter()
+        .filter(|v| !matches!(v.token, Token::Operator(_)))
+        .collect::<Vec<_>>();
+    let end = values.last()?.span.end;
+    let strings = line.strings();
+    match line.token {
+        Token::PlayAlertSound | Token::PlayAlertSoundPositional => {
+            Some((values[0].span.end, sound_name(strings[0])?))
+        }
+        Token::MinimapIcon => match strings[..] {
+            [size, color, shape] => Some((
+                end,
+                format!(
+                    "{} {} {}",
+                    icon_size(size)?,
+                    color.to_lowercase(),
+                    shape.to_lowercase()
+                ),
+            )),
+            _ => None,
+        },
+        Token::SetTextColor | Token::SetBorderColor | Token::SetBackgroundColor => {
+            let [r, g, b, a] = line.color()?;
+            match values.len() {
+                3 => Some((end, format!("#{:02X}{:02X}{:02X}", r, g, b))),
+                _ => Some((end, format!("#{:02X}{:02X}{:02X}{:02X}", r, g, b, a))),
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Hints inside `range`: how many bases each partial `BaseType` string
+/// matches, the names of alert sounds, what a minimap icon looks like and
+/// the hex of colours. Everything comes from the parsed filter and the game
+/// data, never the file on disk.
+pub fn inlay_hints(
+    range: Range,
+    filter: &Filter,
+    text: &str,
+    poe_data: &PoeData,
+) -> Vec<InlayHint> {
+    let lines = LineStarts::new(text);
+    let (start, end) = (lines.byte(range.start), lines.byte(range.end));
+    let mut hints = vec![];
+    for line in filter
+        .lines()
+        .filter(|l| start <= l.span.start && l.span.start <= end)
+    {
+        if line.token == Token::BaseType && line.operator() != Some("==") {
+            for value in line
+                .value
+                .iter()
+                .filter(|v| matches!(v.token, Token::Quotes(_) | Token::Text(_)))
+            {
+                let name = value.value.trim_matches('"');
+                let count = poe_data.bases().matching(name, false).len();
+                let label = match count {
+                    1 => String::from("⟶ 1 base"),
+                    _ => format!("⟶ {} bases", count),
+                };
+                hints.push((value.span.end, label));
+            }
+        } else if let Some(hint) = hint(line) {
+            hints.push(hint);
+        }
+    }
+    hints
+        .into_iter()
+        .map(|(byte, label)| InlayHint {
+            position: lines.position(byte),
+            label,
+            padding_left: Some(true),
+        })
+        .collect()
+}
@@ -1,4 +1,21 @@
+pub mod code_lens;
 pub mod completion;
 pub mod data_parsing;
+pub mod diagnostics;
+pub mod economy;
 pub mod hover;
+pub mod imports;
+pub mod inlay_hints;
+pub mod lookup;
+pub mod mentions;
+pub mod mixins;
 pub mod mode_parsing;
+pub mod rename;
+pub mod signature_help;
+pub mod sounds;
+pub mod strictness;
+pub mod styles;
+pub mod symbols;
+pub mod tiering;
+pub mod variables;
+pub mod workspace;
@@ -0,0 +1,342 @@
+use crate::data_parsing::*;
+use std::collections::{BTreeMap, HashMap};
+
+/// Something in the game data that is looked up by name.
+pub trait Named {
+    fn name(&self) -> &str;
+}
+
+impl Named for ItemClass {
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Named for BaseItem {
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Named for Mod {
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Named for Gem {
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Named for Prophecy {
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Named for Enchantment {
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Named for UniqueItem {
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Named for DivinationCard {
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// Positions of the items of one table, keyed by lowercased name, with the
+/// names also keyed by each run of three bytes in them for substring lookups.
+#[derive(Default, Clone, Debug)]
+pub struct NameIndex {
+    /// Lowercased names in order, each with the positions of its items.
+    names: Vec<(String, Vec<usize>)>,
+    /// Positions in `names` of the names holding each three bytes.
+    grams: HashMap<[u8; 3], Vec<usize>>,
+}
+
+impl NameIndex {
+    pub fn new<T: Named>(items: &[T]) -> Self {
+        let mut names = BTreeMap::<String, Vec<usize>>::new();
+        for (i, item) in items.iter().enumerate() {
+            if !item.name().is_empty() {
+                names.entry(item.name().to_lowercase()).or_default().push(i);
+            }
+        }
+        let names = names.into_iter().collect::<Vec<_>>();
+        let mut grams = HashMap::<[u8; 3], Vec<usize>>::new();
+        for (i, (name, _)) in names.iter().enumerate() {
+            for gram in trigrams(name) {
+                let holding = grams.entry(gram).or_default();
+                if holding.last() != Some(&i) {
+                    holding.push(i);
+                }
+            }
+        }
+        NameIndex { names, grams }
+    }
+
+    fn get(&self, key: &str) -> Option<&Vec<usize>> {
+        self.names
+            .binary_search_by(|(name, _)| name.as_str().cmp(key))
+            .ok()
+            .map(|i| &self.names[i].1)
+    }
+
+    /// The names starting with the lowercased `query`.
+    fn starting_with<'a>(
+        &'a self,
+        query: &'a str,
+    ) -> impl Iterator<Item = &'a (String, Vec<usize>)> + 'a {
+        let from = self
+            .names
+            .partition_point(|(name, _)| name.as_str() < query);
+        self.names[from..]
+            .iter()
+            .take_while(move |(name, _)| name.starts_with(query))
+    }
+
+    /// The names containing the lowercased `query`, in order. Only the names
+    /// holding its rarest three bytes are checked; shorter queries check them all.
+    fn containing<'a>(
+        &'a self,
+        query: &'a str,
+    ) -> Box<dyn Iterator<Item = &'a (String, Vec<usize>)> + 'a> {
+        if query.len() < 3 {
+            return Box::new(self.names.iter().filter(move |(n, _)| n.contains(query)));
+        }
+        let rarest = trigrams(query)
+            .map(|gram| self.grams.get(&gram).map_or(&[][..], |h| h.as_slice()))
+            .min_by_key(|holding| holding.len())
+            .unwrap_or_default();
+        Box::new(
+            rarest
+                .iter()
+                .map(move |i| &self.names[*i])
+                .filter(move |(n, _)| n.contains(query)),
+        )
+    }
+}
+
+fn trigrams(name: &str) -> impl Iterator<Item = [u8; 3]> + '_ {
+    name.as_bytes().windows(3).map(|w| [w[0], w[1], w[2]])
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum MatchKind {
+    Fuzzy,
+    Substring,
+    Prefix,
+    Exact,
+}
+
+/// A search result; a higher `score` is a better match.
+#[derive(Debug)]
+pub struct Scored<'a, T> {
+    pub item: &'a T,
+    pub kind: MatchKind,
+    pub score: i32,
+}
+
+impl<'a, T> Clone for Scored<'a, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, T> Copy for Scored<'a, T> {}
+
+/// One table of the game data together with its name index.
+pub struct Table<'a, T> {
+    items: &'a [T],
+    index: &'a NameIndex,
+    sample: bool,
+}
+
+impl<'a, T: Named> Table<'a, T> {
+    pub fn new(items: &'a [T], index: &'a NameIndex) -> Self {
+        Table {
+            items,
+            index,
+            sample: false,
+        }
+    }
+
+    /// Marks the table as holding only some of the game's names, so a name
+    /// missing from it may still be right.
+    pub fn sampled(self, sample: bool) -> Self {
+        Table { sample, ..self }
+    }
+
+    pub fn is_sample(&self) -> bool {
+        self.sample
+    }
+
+    pub fn items(&self) -> &'a [T] {
+        self.items
+    }
+
+    /// Items named exactly `name`, as the game does for `==`.
+    pub fn exact(&self, name: &str) -> Vec<&'a T> {
+        self.positions(&name.to_lowercase())
+            .filter(|item| item.name() == name)
+            .collect()
+    }
+
+    /// The items a filter value selects: exact for `==`, otherwise every
+    /// name containing the value, like the game's partial matching. The
+    /// index narrows the names down before the case-sensitive check.
+    pub fn matching(&self, text: &str, exact: bool) -> Vec<&'a T> {
+        if exact {
+            return self.exact(text);
+        }
+        let query = text.to_lowercase();
+        self.index
+            .containing(&query)
+            .flat_map(|(_, positions)| positions.iter().map(|i| &self.items[*i]))
+            .filter(|item| item.name().contains(text))
+            .collect()
+    }
+
+    /// Items whose name starts with `query`, ignoring case.
+    pub fn prefix(&self, query: &str) -> Vec<Scored<'a, T>> {
+        let query = query.to_lowercase();
+        let mut out = self
+            .index
+            .starting_with(&query)
+            .flat_map(|(name, positions)| {
+                let kind = if *name == query {
+                    MatchKind::Exact
+                } else {
+                    MatchKind::Prefix
+                };
+                let score = score(kind, name.len() - query.len());
+                positions.iter().map(move |i| (*i, kind, score))
+            })
+            .map(|(i, kind, score)| Scored {
+                item: &self.items[i],
+                kind,
+                score,
+            })
+            .collect::<Vec<_>>();
+        sort(&mut out);
+        out
+    }
+
+    /// Items whose name contains `query`, ignoring case.
+    pub fn substring(&self, query: &str) -> Vec<Scored<'a, T>> {
+        let query = query.to_lowercase();
+        let mut out = self
+            .index
+            .containing(&query)
+            .filter_map(|(name, positions)| {
+                let at = name.find(&query)?;
+                let kind = match at {
+                    0 if *name == query => MatchKind::Exact,
+                    0 => MatchKind::Prefix,
+                    _ => MatchKind::Substring,
+                };
+                Some((positions, kind, score(kind, name.len() - query.len() + at)))
+            })
+            .flat_map(|(positions, kind, score)| {
+                positions.iter().map(move |i| Scored {
+                    item: &self.items[*i],
+                    kind,
+                    score,
+                })
+            })
+            .collect::<Vec<_>>();
+        sort(&mut out);
+        out
+    }
+
+    /// Items whose name, or the start of it, is a few typos away from `query`.
+    pub fn fuzzy(&self, query: &str) -> Vec<Scored<'a, T>> {
+        let query = query.to_lowercase().chars().collect::<Vec<_>>();
+        if query.is_empty() {
+            return vec![];
+        }
+        let allowed = (query.len() / 4).max(1);
+        let mut out = vec![];
+        for (name, positions) in self.index.names.iter() {
+            let name = name.chars().collect::<Vec<_>>();
+            let whole = distance(&query, &name);
+            let start = distance(&query, &name[..query.len().min(name.len())]) + 1;
+            let typos = whole.min(start);
+            if typos > allowed {
+                continue;
+            }
+            for i in positions.iter() {
+                out.push(Scored {
+                    item: &self.items[*i],
+                    kind: MatchKind::Fuzzy,
+                    score: score(MatchKind::Fuzzy, typos * 10 + name.len()),
+                });
+            }
+        }
+        sort(&mut out);
+        out
+    }
+
+    /// Prefix, substring and fuzzy results together, best first.
+    pub fn search(&self, query: &str) -> Vec<Scored<'a, T>> {
+        let mut out = self.substring(query);
+        for found in self.fuzzy(query) {
+            if !out.iter().any(|o| std::ptr::eq(o.item, found.item)) {
+                out.push(found);
+            }
+        }
+        sort(&mut out);
+        out
+    }
+
+    fn positions(&self, key: &str) -> impl Iterator<Item = &'a T> + '_ {
+        self.index
+            .get(key)
+            .into_iter()
+            .flatten()
+            .map(move |i| &self.items[*i])
+    }
+}
+
+/// Each kind of match outranks the next; within a kind fewer extra
+/// characters score higher.
+fn score(kind: MatchKind, extra: usize) -> i32 {
+    (kind as i32 + 1) * 1000 - (extra.min(999) as i32)
+}
+
+fn sort<T: Named>(results: &mut Vec<Scored<'_, T>>) {
+    results.sort_by(|a, b| {
+        b.score
+            .cmp(&a.score)
+            .then_with(|| a.item.name().cmp(b.item.name()))
+    });
+}
+
+/// Levenshtein distance between two strings.
+fn distance(a: &[char], b: &[char]) -> usize {
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.iter().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let current = row[j + 1];
+            row[j + 1] = if ca == cb {
+                previous
+            } else {
+                1 + previous.min(row[j]).min(current)
+            };
+            previous = current;
+        }
+    }
+    row[b.len()]
+}
@@ -0,0 +1,175 @@
+use crate::hover::{byte_pos_in_string, position_in_string};
+use crate::mode_parsing::{Filter, Token, TokenAndSpan};
+use lsp_types::*;
+use std::path::Path;
+
+/// Keywords whose names, like `"Exalted Orb"`, are looked up across blocks.
+pub const NAMED: &[Token] = &[
+    Token::BaseType,
+    Token::Class,
+    Token::Prophecy,
+    Token::HasExplicitMod,
+    Token::HasEnchantment,
+];
+
+const COLORS: &[Token] = &[
+    Token::SetTextColor,
+    Token::SetBorderColor,
+    Token::SetBackgroundColor,
+];
+
+/// A value that other blocks may repeat.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Mention {
+    /// A name on a line like `BaseType "Exalted Orb"`, without its quotes.
+    Name(Token, String),
+    /// The red, green and blue of a colour line; the alpha is left out.
+    Color([u8; 3]),
+    /// A `$name->value` tag in a block's header comment, by name and value.
+    Tag(String, String),
+}
+
+/// The span of the red, green and blue values of a colour line.
+fn color_span(line: &TokenAndSpan) -> Option<std::ops::Range<usize>> {
+    if !COLORS.contains(&line.token) {
+        return None;
+    }
+    let values = line
+        .value
+        .iter()
+        .filter(|v| !matches!(v.token, Token::Operator(_)))
+        .collect::<Vec<_>>();
+    Some(values.first()?.span.start..values.get(2)?.span.end)
+}
+
+fn rgb(line: &TokenAndSpan) -> Option<[u8; 3]> {
+    let [r, g, b, _] = line.color()?;
+    Some([r, g, b])
+}
+
+/// The mention at `byte` with its span there; for a tag, the span of its value.
+pub fn mention_at(
+    filter: &Filter,
+    text: &str,
+    byte: usize,
+) -> Option<(Mention, std::ops::Range<usize>)> {
+    let tag = filter
+        .vec
+        .iter()
+        .flat_map(|block| block.tags(text))
+        .find(|(name, _, span)| span.start - name.len() - 3 <= byte && span.end >= byte);
+    if let Some((name, value, span)) = tag {
+        return Some((Mention::Tag(name.to_string(), value.to_string()), span));
+    }
+    filter.lines().find_map(|line| {
+        let value = line
+            .value
+            .iter()
+            .find(|v| v.span.start <= byte && v.span.end >= byte)?;
+        match &value.token {
+            Token::Quotes(name) | Token::Text(name) if NAMED.contains(&line.token) => Some((
+                Mention::Name(line.token.clone(), name.trim_matches('"').to_string()),
+                value.span.clone(),
+            )),
+            _ => Some((Mention::Color(rgb(line)?), color_span(line)?)),
+        }
+    })
+}
+
+/// Every span in `filter` mentioning the same name on the same keyword, the
+/// same colour on any colour line, or the value of the same tag. A name counts
+/// as mentioned wherever the game would match it, so `BaseType "Orb"` mentions
+/// `"Exalted Orb"` but `BaseType == "Orb"` does not.
+pub fn mentions(filter: &Filter, text: &str, mention: &Mention) -> Vec<std::ops::Range<usize>> {
+    if let Mention::Tag(name, value) = mention {
+        return filter
+            .vec
+            .iter()
+            .flat_map(|block| block.tags(text))
+            .filter(|(n, v, _)| n == name && v == value)
+            .map(|(_, _, span)| span)
+            .collect();
+    }
+    filter
+        .lines()
+        .flat_map(|line| match mention {
+            Mention::Name(token, name) if line.token == *token && line.matches_text(name) => {
+                let exact = line.operator() == Some("==");
+                line.value
+                    .iter()
+                    .filter(|v| matches!(v.token, Token::Quotes(_) | Token::Text(_)))
+                    .map(|v| (v, v.value.trim_matches('"')))
+                    .filter(|(_, s)| if exact { s == name } else { name.contains(s) })
+                    .map(|(v, _)| v.span.clone())
+                    .collect()
+            }
+            Mention::Color(color) if rgb(line).as_ref() == Some(color) => {
+                color_span(line).into_iter().collect()
+            }
+            _ => vec![],
+        })
+        .collect()
+}
+
+fn range(span: &std::ops::Range<usize>, text: &str) -> Range {
+    Range::new(
+        position_in_string(span.start, text),
+        position_in_string(span.end, text),
+    )
+}
+
+/// Every block here and in `others` mentioning the base, class, colour or
+/// tag under the cursor.
+pub fn references<'a>(
+    params: ReferenceParams,
+    filter: &Filter,
+    text: &str,
+    others: impl IntoIterator<Item = (&'a Path, &'a Filter, &'a str)>,
+) -> Vec<Location> {
+    let position = params.text_document_position;
+    let byte = byte_pos_in_string(
+        position.position.line as usize,
+        position.position.character as usize,
+        text.to_string(),
+    );
+    let mention = match mention_at(filter, text, byte) {
+        Some((mention, _)) => mention,
+        None => return vec![],
+    };
+    let uri = position.text_document.uri;
+    let here = mentions(filter, text, &mention)
+        .into_iter()
+        .map(|span| Location::new(uri.clone(), range(&span, text)));
+    let elsewhere = others.into_iter().flat_map(|(path, filter, text)| {
+        let uri = Url::from_file_path(path).ok();
+        mentions(filter, text, &mention)
+            .into_iter()
+            .filter_map(move |span| Some(Location::new(uri.clone()?, range(&span, text))))
+    });
+    here.chain(elsewhere).collect()
+}
+
+/// The mentions of the value under the cursor in this document.
+pub fn highlights(
+    params: DocumentHighlightParams,
+    filter: &Filter,
+    text: &str,
+) -> Vec<DocumentHighlight> {
+    let position = params.text_document_position_params.position;
+    let byte = byte_pos_in_string(
+        position.line as usize,
+        position.character as usize,
+        text.to_string(),
+    );
+    let mention = match mention_at(filter, text, byte) {
+        Some((mention, _)) => mention,
+        None => return vec![],
+    };
+    mentions(filter, text, &mention)
+        .iter()
+        .map(|span| DocumentHighlight {
+            range: range(span, text),
+            kind: Some(DocumentHighlightKind::Text),
+        })
+        .collect()
+}
@@ -0,0 +1,218 @@
+use crate::hover::{byte_pos_in_string, position_in_string};
+use crate::mode_parsing::{
+    apply_edits, parse, Filter, FilterBlock, KeywordType, Mixin, Token, TokenAndSpan,
+};
+use lsp_types::*;
+use std::collections::HashMap;
+
+/// The lines of a mixin as written, like `SetFontSize 45`.
+pub fn lines<'a>(mixin: &Mixin, text: &'a str) -> Vec<&'a str> {
+    mixin
+        .block
+        .keywords
+        .iter()
+        .map(|line| line_text(line, text))
+        .collect()
+}
+
+fn line_end(line: &TokenAndSpan) -> usize {
+    line.value.last().map_or(line.span.end, |v| v.span.end)
+}
+
+fn line_text<'a>(line: &TokenAndSpan, text: &'a str) -> &'a str {
+    &text[line.span.start..line_end(line)]
+}
+
+fn line_start(text: &str, byte: usize) -> usize {
+    text[..byte].rfind('\n').map_or(0, |i| i + 1)
+}
+
+/// The filter the game loads: every `$name` line is replaced by the lines of
+/// its mixin, indented like the line it replaces, and the mixins themselves
+/// are removed. Lines the block sets itself win over the mixin's, and
+/// mixins may use other mixins. Undefined mixins are left as written.
+pub fn expand(text: &str) -> String {
+    let filter = parse(text);
+    let mut edits = vec![];
+    for mixin in filter.mixins.iter() {
+        let start = line_start(text, mixin.block.bspan.start);
+        let end = mixin.block.end().max(mixin.span.end);
+        let blank = text[end..]
+            .split_inclusive('\n')
+            .enumerate()
+            .take_while(|(i, line)| *i == 0 || line.trim().is_empty())
+            .map(|(_, line)| line.len())
+            .sum::<usize>();
+        edits.push((start..end + blank, String::new()));
+    }
+    for block in filter.vec.iter() {
+        let own = block
+            .keywords
+            .iter()
+            .map(|line| &line.token)
+            .filter(|token| !matches!(token, Token::Variable(_)))
+            .collect::<Vec<_>>();
+        for line in block.keywords.iter() {
+            let name = match &line.token {
+                Token::Variable(name) => name,
+                _ => continue,
+            };
+            if let Some(lines) = mixin_lines(&filter, name, text, &own, &mut vec![]) {
+                let start = line_start(text, line.span.start);
+                let indent = &text[start..line.span.start];
+                let lines = lines
+                    .iter()
+                    .map(|l| format!("{}{}", indent, l))
+                    .collect::<Vec<_>>();
+                edits.push((start..line.span.end, lines.join("\n")));
+            }
+        }
+    }
+    apply_edits(text, edits)
+}
+
+/// The lines `$name` stands for in a block setting `own` itself. `stack`
+/// holds the mixins being expanded, so one using itself stays as written.
+fn mixin_lines(
+    filter: &Filter,
+    name: &str,
+    text: &str,
+    own: &[&Token],
+    stack: &mut Vec<String>,
+) -> Option<Vec<String>> {
+    if stack.iter().any(|n| n == name) {
+        return None;
+    }
+    let mixin = filter.mixin(name)?;
+    stack.push(name.to_string());
+    let mut out = vec![];
+    for line in mixin.block.keywords.iter() {
+        match &line.token {
+            Token::Variable(inner) => match mixin_lines(filter, inner, text, own, stack) {
+                Some(lines) => out.extend(lines),
+                None => out.push(line_text(line, text).to_string()),
+            },
+            token if own.contains(&token) => {}
+            _ => out.push(line_text(line, text).to_string()),
+        }
+    }
+    stack.pop();
+    Some(out)
+}
+
+/// The block's action lines with their values separated by single spaces.
+fn actions<'a>(block: &'a FilterBlock, text: &str) -> Vec<(&'a TokenAndSpan, String)> {
+    block
+        .keywords
+        .iter()
+        .filter(|line| line.token.keyword_type() == Some(KeywordType::Actions))
+        .map(|line| {
+            let words = line_text(line, text).split_whitespace();
+            (line, words.collect::<Vec<_>>().join(" "))
+        })
+        .collect()
+}
+
+/// A `$type_tier` name from the tags all `blocks` share that nothing here
+/// uses yet.
+fn mixin_name(filter: &Filter, blocks: &[&FilterBlock], text: &str) -> String {
+    let shared = |name: &str| {
+        let value = blocks[0].tag(text, name)?;
+        blocks[1..]
+            .iter()
+            .all(|b| b.tag(text, name) == Some(value))
+            .then_some(value)
+    };
+    let base = match (shared("type"), shared("tier")) {
+        (Some(kind), Some(tier)) => format!("{}_{}", kind, tier),
+        (Some(tag), None) | (None, Some(tag)) => tag.to_string(),
+        (None, None) => String::from("mixin"),
+    };
+    let base = base
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    let mut name = format!("${}", base);
+    let mut n = 1;
+    while filter.definition_span(&name).is_some() {
+        n += 1;
+        name = format!("${}_{}", base, n);
+    }
+    name
+}
+
+/// A code action moving the actions of the block under the cursor into a new
+/// mixin, offered when other blocks repeat exactly the same actions. Every
+/// such block gets a `$name` line in their place.
+pub fn extract(params: &CodeActionParams, filter: &Filter, text: &str) -> Vec<CodeActionOrCommand> {
+    let byte = byte_pos_in_string(
+        params.range.start.line as usize,
+        params.range.start.character as usize,
+        text.to_string(),
+    );
+    let block = match filter.search_block(byte) {
+        Some(block)
+            if block.block.is_some()
+                && block.block != Some(Token::Mixin)
+                && byte <= block.end() =>
+        {
+            block
+        }
+        _ => return vec![],
+    };
+    let set = actions(block, text)
+        .into_iter()
+        .map(|(_, line)| line)
+        .collect::<Vec<_>>();
+    if set.is_empty() {
+        return vec![];
+    }
+    let blocks = filter
+        .vec
+        .iter()
+        .filter(|b| b.block.is_some())
+        .filter(|b| actions(b, text).iter().map(|(_, line)| line).eq(set.iter()))
+        .collect::<Vec<_>>();
+    if blocks.len() < 2 {
+        return vec![];
+    }
+    let name = mixin_name(filter, &blocks, text);
+    let edit = |span: std::ops::Range<usize>, new_text: String| {
+        TextEdit::new(
+            Range::new(
+                position_in_string(span.start, text),
+                position_in_string(span.end, text),
+            ),
+            new_text,
+        )
+    };
+    let at = line_start(text, blocks[0].bspan.start);
+    let definition = set
+        .iter()
+        .map(|line| format!("\n\t{}", line))
+        .collect::<String>();
+    let mut edits = vec![edit(at..at, format!("Mixin {}{}\n\n", name, definition))];
+    for block in blocks.iter() {
+        for (i, (line, _)) in actions(block, text).into_iter().enumerate() {
+            let start = line_start(text, line.span.start);
+            if i == 0 {
+                let indent = &text[start..line.span.start];
+                edits.push(edit(start..line_end(line), format!("{}{}", indent, name)));
+            } else {
+                edits.push(edit(start - 1..line_end(line), String::new()));
+            }
+        }
+    }
+    let mut changes = HashMap::new();
+    changes.insert(params.text_document.uri.clone(), edits);
+    vec![CodeActionOrCommand::CodeAction(CodeAction {
+        title: format!(
+            "Extract actions into mixin {} ({} blocks)",
+            name,
+            blocks.len()
+        ),
+        kind: Some(code_action_kind::REFACTOR_EXTRACT.to_string()),
+        edit: Some(WorkspaceEdit::new(changes)),
+        ..CodeAction::default()
+    })]
+}
@@ -1,9 +1,11 @@
 use crate::data_parsing::*;
 use logos::{Lexer, Logos};
 use lsp_types::*;
+use std::collections::HashSet;
 
-#[derive(Clone, Debug, Eq, PartialEq, Hash, Logos)]
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash, Logos)]
 pub enum Token {
+    #[default]
     #[error]
     Error,
     #[token("Show")]
@@ -12,6 +14,10 @@ pub enum Token {
     Hide,
     #[token("Continue")]
     Continue,
+    #[token("Import")]
+    Import,
+    #[token("Mixin")]
+    Mixin,
     #[token("#", ignore_comments)]
     Hash,
     #[regex(" | |", logos::skip)]
@@ -56,6 +62,8 @@ pub enum Token {
     StackSize,
     #[token("GemLevel")]
     GemLevel,
+    #[token("GemQualityType")]
+    GemQualityType,
     #[token("Identified")]
     Identified,
     #[token("Corrupted")]
@@ -110,8 +118,37 @@ pub enum Token {
     Boolean(String),
     #[regex("[a-zA-Z]+", |s| s.slice().to_string())]
     Text(String),
+    #[regex("[<>]=?|==?|!=?", |s| s.slice().to_string())]
+    Operator(String),
+    #[regex("\\$[a-zA-Z0-9_]+", |s| s.slice().to_string())]
+    Variable(String),
 }
 
+pub const OPERATORS: &[&str] = &["<", "<=", ">", ">=", "=", "==", "!", "!="];
+pub const BOOLEANS: &[&str] = &["True", "False"];
+pub const RARITIES: &[&str] = &["Normal", "Magic", "Rare", "Unique"];
+pub const INFLUENCES: &[&str] = &[
+    "Shaper", "Elder", "Crusader", "Hunter", "Redeemer", "Warlord",
+];
+pub const COLORS: &[&str] = &[
+    "Red", "Green", "Blue", "Brown", "White", "Yellow", "Cyan", "Grey", "Orange", "Pink", "Purple",
+];
+pub const ICON_SIZES: &[&str] = &["0", "1", "2"];
+pub const ICON_SHAPES: &[&str] = &[
+    "Circle",
+    "Diamond",
+    "Hexagon",
+    "Square",
+    "Star",
+    "Triangle",
+    "Cross",
+    "Moon",
+    "Raindrop",
+    "Kite",
+    "Pentagon",
+    "UpsideDownHouse",
+];
+
 #[derive(Clone, Debug, Eq, PartialEq, Hash)]
 pub enum KeywordType {
     Conditions,
@@ -142,6 +179,7 @@ impl KeywordType {
                 Token::HasEnchantment,
                 Token::StackSize,
                 Token::GemLevel,
+                Token::GemQualityType,
                 Token::Identified,
                 Token::Corrupted,
                 Token::CorruptedMods,
@@ -166,8 +204,11 @@ impl KeywordType {
                 Token::MinimapIcon,
                 Token::PlayEffect,
             ],
-            KeywordType::Block => vec![Token::Show, Token::Hide, Token::Continue],
-            KeywordType::Operations => vec![Token::Error],
+            KeywordType::Block => vec![Token::Show, Token::Hide, Token::Continue, Token::Mixin],
+            KeywordType::Operations => OPERATORS
+                .iter()
+                .map(|o| Token::Operator(o.to_string()))
+                .collect(),
             KeywordType::Values(_) => vec![
                 Token::Numbers(String::new()),
                 Token::Quotes(String::new()),
@@ -179,9 +220,12 @@ impl KeywordType {
 }
 
 impl Token {
-    pub fn create_completion_item(&self) -> CompletionItem {
+    /// The completion for this keyword, inserting its snippet when the client
+    /// supports snippets and the plain label otherwise.
+    pub fn create_completion_item(&self, snippets: bool) -> CompletionItem {
+        let snippet = self.snippet().filter(|_| snippets);
         CompletionItem {
-            label: format!("{:?}", self),
+            label: self.label(),
             kind: {
                 if let Some(keyword) = self.keyword_type() {
                     match keyword {
@@ -196,71 +240,187 @@ impl Token {
                 }
             },
             detail: Some(self.description()),
+            insert_text_format: snippet.as_ref().map(|_| InsertTextFormat::Snippet),
+            insert_text: snippet,
             ..CompletionItem::default()
         }
     }
 
-    pub fn valid_values(&self, poe_data: PoeData) -> Option<Vec<Record>> {
+    /// Names accepted by this keyword, borrowed from `poe_data` or the
+    /// built-in lists.
+    pub fn valid_values<'a>(
+        &self,
+        poe_data: &'a PoeData,
+    ) -> Option<Box<dyn Iterator<Item = &'a str> + 'a>> {
         match self {
-            Token::HasExplicitMod => Some(poe_data.mods),
-            Token::Class => Some(poe_data.classes),
-            Token::BaseType => Some(poe_data.bases),
-            Token::PlayEffect => Some(vec![
-                Record {name: Some("Red".to_string()), ..Default::default()},
-                Record {name: Some("Green".to_string()), ..Default::default()},
-                Record {name: Some("Blue".to_string()), ..Default::default()},
-                Record {name: Some("Brown".to_string()), ..Default::default()},
-                Record {name: Some("White".to_string()), ..Default::default()},
-                Record {name: Some("Yellow".to_string()), ..Default::default()},
-                Record {name: Some("Cyan".to_string()), ..Default::default()},
-                Record {name: Some("Grey".to_string()), ..Default::default()},
-                Record {name: Some("Orange".to_string()), ..Default::default()},
-                Record {name: Some("Pink".to_string()), ..Default::default()},
-                Record {name: Some("Purple".to_string()), ..Default::default()},
-                ]),
-            Token::MinimapIcon => Some(vec![
-                Record {name: Some("Red".to_string()), ..Default::default()},
-                Record {name: Some("Green".to_string()), ..Default::default()},
-                Record {name: Some("Blue".to_string()), ..Default::default()},
-                Record {name: Some("Brown".to_string()), ..Default::default()},
-                Record {name: Some("White".to_string()), ..Default::default()},
-                Record {name: Some("Yellow".to_string()), ..Default::default()},
-                Record {name: Some("Cyan".to_string()), ..Default::default()},
-                Record {name: Some("Grey".to_string()), ..Default::default()},
-                Record {name: Some("Orange".to_string()), ..Default::default()},
-                Record {name: Some("Pink".to_string()), ..Default::default()},
-                Record {name: Some("Purple".to_string()), ..Default::default()},
-                Record {name: Some("Circle".to_string()), ..Default::default()},
-                Record {name: Some("Diamond".to_string()), ..Default::default()},
-                Record {name: Some("Hexagon".to_string()), ..Default::default()},
-                Record {name: Some("Square".to_string()), ..Default::default()},
-                Record {name: Some("Star".to_string()), ..Default::default()},
-                Record {name: Some("Triangle".to_string()), ..Default::default()},
-                Record {name: Some("Cross".to_string()), ..Default::default()},
-                Record {name: Some("Moon".to_string()), ..Default::default()},
-                Record {name: Some("Raindrop".to_string()), ..Default::default()},
-                Record {name: Some("Kite".to_string()), ..Default::default()},
-                Record {name: Some("Pentagon".to_string()), ..Default::default()},
-                Record {name: Some("UpsideDownHouse".to_string()), ..Default::default()},
-                ]),
-            Token::Rarity => Some(vec![
-                Record {name: Some("Normal".to_string()), ..Default::default()},
-                Record {name: Some("Magic".to_string()), ..Default::default()},
-                Record {name: Some("Rare".to_string()), ..Default::default()},
-                Record {name: Some("Unique".to_string()), ..Default::default()},
-            ]),
-            Token::HasInfluence => Some(vec![
-                Record {name: Some("Shaper".to_string()), ..Default::default()},
-                Record {name: Some("Elder".to_string()), ..Default::default()},
-                Record {name: Some("Crusader".to_string()), ..Default::default()},
-                Record {name: Some("Hunter".to_string()), ..Default::default()},
-                Record {name: Some("Redeemer".to_string()), ..Default::default()},
-                Record {name: Some("Warlord".to_string()), ..Default::default()},
-            ]),
+            Token::HasExplicitMod => {
+                let mut seen = HashSet::new();
+                Some(Box::new(
+                    poe_data
+                        .mods()
+                        .items()
+                        .iter()
+                        .map(|m| m.name.as_str())
+                        .filter(move |n| !n.is_empty() && seen.insert(*n)),
+                ))
+            }
+            Token::Class => Some(Box::new(
+                poe_data.classes().items().iter().map(|c| c.name.as_str()),
+            )),
+            Token::BaseType => Some(Box::new(
+                poe_data.bases().items().iter().map(|b| b.name.as_str()),
+            )),
+            Token::Prophecy => Some(Box::new(
+                poe_data
+                    .prophecies()
+                    .items()
+                    .iter()
+                    .map(|p| p.name.as_str()),
+            )),
+            Token::HasEnchantment => Some(Box::new(
+                poe_data
+                    .enchantments()
+                    .items()
+                    .iter()
+                    .map(|e| e.name.as_str()),
+            )),
+            Token::GemQualityType => Some(Box::new(QUALITY_TYPES.iter().copied())),
+            Token::PlayEffect => Some(Box::new(COLORS.iter().copied())),
+            Token::MinimapIcon => Some(Box::new(COLORS.iter().chain(ICON_SHAPES).copied())),
+            Token::Rarity => Some(Box::new(RARITIES.iter().copied())),
+            Token::HasInfluence => Some(Box::new(INFLUENCES.iter().copied())),
             _ => None,
         }
     }
 
+    /// The values that can be typed into argument `index` of this keyword.
+    /// `operator` is whether the line already has a comparison operator.
+    pub fn argument_values(&self, index: usize, operator: bool) -> Vec<Token> {
+        let arguments = self.arguments();
+        let slot = arguments
+            .iter()
+            .filter(|a| **a != Arguments::Operator)
+            .nth(index)
+            .or_else(|| arguments.last().filter(|a| a.repeats()));
+        let values = slot.map(|a| a.values()).unwrap_or_default();
+        if index == 0 && !operator && arguments.first() == Some(&Arguments::Operator) {
+            Arguments::Operator
+                .values()
+                .into_iter()
+                .chain(values)
+                .collect()
+        } else {
+            values
+        }
+    }
+
+    /// The positional arguments of a condition or action, in order.
+    pub fn arguments(&self) -> Vec<Arguments> {
+        match self {
+            Token::AreaLevel | Token::ItemLevel | Token::DropLevel => {
+                vec![Arguments::Operator, Arguments::ValidNumeric]
+            }
+            Token::Quality => vec![Arguments::Operator, Arguments::ValidQuality],
+            Token::Rarity => vec![Arguments::Operator, Arguments::ValidRarity],
+            Token::Class => vec![Arguments::ValidClass],
+            Token::BaseType | Token::HasExplicitMod => vec![Arguments::ValidItem],
+            Token::Prophecy => vec![Arguments::ValidProphecy],
+            Token::LinkedSockets => vec![Arguments::Operator, Arguments::ValidLinks],
+            Token::SocketGroup => vec![Arguments::Operator, Arguments::ValidLSockets],
+            Token::Sockets => vec![Arguments::Operator, Arguments::ValidSockets],
+            Token::Height => vec![Arguments::Operator, Arguments::ValidHeight],
+            Token::Width => vec![Arguments::Operator, Arguments::ValidWidth],
+            Token::HasEnchantment => vec![Arguments::Enchant],
+            Token::StackSize => vec![Arguments::Operator, Arguments::StackSize],
+            Token::GemLevel => vec![Arguments::Operator, Arguments::GemLevel],
+            Token::GemQualityType => vec![Arguments::QualityType],
+            Token::CorruptedMods => vec![Arguments::Operator, Arguments::CorruptedMods],
+            Token::HasInfluence => vec![Arguments::HasInfluence],
+            Token::MapTier => vec![Arguments::Operator, Arguments::MapTier],
+            Token::AnyEnchantment
+            | Token::Identified
+            | Token::Corrupted
+            | Token::Mirrored
+            | Token::ElderItem
+            | Token::ShaperItem
+            | Token::FracturedItem
+            | Token::SynthesisedItem
+            | Token::ShapedMap => vec![Arguments::Boolean],
+            Token::SetBorderColor | Token::SetTextColor | Token::SetBackgroundColor => vec![
+                Arguments::Red,
+                Arguments::Green,
+                Arguments::Blue,
+                Arguments::Alpha,
+            ],
+            Token::SetFontSize => vec![Arguments::FontSize],
+            Token::PlayAlertSound | Token::PlayAlertSoundPositional => {
+                vec![Arguments::SoundId, Arguments::Volume]
+            }
+            Token::CustomAlertSound => vec![Arguments::CustomSound],
+            Token::PlayEffect => vec![Arguments::EffectColor, Arguments::Temp],
+            Token::MinimapIcon => vec![
+                Arguments::IconSize,
+                Arguments::IconColor,
+                Arguments::IconShape,
+            ],
+            _ => vec![],
+        }
+    }
+
+    /// LSP snippet for the keyword: a block skeleton for `Show`/`Hide` and
+    /// one tab stop per argument for actions.
+    pub fn snippet(&self) -> Option<String> {
+        match self {
+            Token::Show | Token::Hide => {
+                let (class, next) = Token::Class.tab_stops(1);
+                let (base, next) = Token::BaseType.tab_stops(next);
+                let (size, next) = Token::SetFontSize.tab_stops(next);
+                let (color, _) = Token::SetTextColor.tab_stops(next);
+                Some(format!(
+                    "{}\n\t{}\n\t{}\n\t{}\n\t{}\n\t$0",
+                    self.label(),
+                    class,
+                    base,
+                    size,
+                    color
+                ))
+            }
+            _ => match self.keyword_type() {
+                Some(KeywordType::Actions) if !self.arguments().is_empty() => {
+                    Some(self.tab_stops(1).0)
+                }
+                _ => None,
+            },
+        }
+    }
+
+    /// The keyword followed by its argument placeholders, numbered from `first`.
+    /// Also returns the next free tab stop.
+    fn tab_stops(&self, first: usize) -> (String, usize) {
+        let mut next = first;
+        let mut out = self.label();
+        for argument in self.arguments() {
+            if let Some(placeholder) = argument.placeholder(next) {
+                out.push(' ');
+                out.push_str(&placeholder);
+                next += 1;
+            }
+        }
+        (out, next)
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            Token::Numbers(s)
+            | Token::Quotes(s)
+            | Token::Boolean(s)
+            | Token::Text(s)
+            | Token::Operator(s)
+            | Token::Variable(s) => s.to_owned(),
+            _ => format!("{:?}", self),
+        }
+    }
+
     pub fn keyword_type(&self) -> Option<KeywordType> {
         match self {
             Token::Error => None,
@@ -271,6 +431,8 @@ impl Token {
             Token::Show => Some(KeywordType::Block),
             Token::Hide => Some(KeywordType::Block),
             Token::Continue => Some(KeywordType::Block),
+            Token::Import => None,
+            Token::Mixin => Some(KeywordType::Block),
             //contisitons
             Token::AreaLevel => Some(KeywordType::Conditions),
             Token::ItemLevel => Some(KeywordType::Conditions),
@@ -290,6 +452,7 @@ impl Token {
             Token::HasEnchantment => Some(KeywordType::Conditions),
             Token::StackSize => Some(KeywordType::Conditions),
             Token::GemLevel => Some(KeywordType::Conditions),
+            Token::GemQualityType => Some(KeywordType::Conditions),
             Token::Identified => Some(KeywordType::Conditions),
             Token::Corrupted => Some(KeywordType::Conditions),
             Token::CorruptedMods => Some(KeywordType::Conditions),
@@ -317,23 +480,34 @@ impl Token {
             Token::Quotes(s) => Some(KeywordType::Values(s.to_owned())),
             Token::Boolean(s) => Some(KeywordType::Values(s.to_owned())),
             Token::Text(s) => Some(KeywordType::Values(s.to_owned())),
+            Token::Variable(s) => Some(KeywordType::Values(s.to_owned())),
+            Token::Operator(_) => Some(KeywordType::Operations),
         }
     }
 }
 
-impl Default for Token {
-    fn default() -> Self {
-        Token::Error
-    }
+fn texts(names: &[&str]) -> Vec<Token> {
+    names.iter().map(|n| Token::Text(n.to_string())).collect()
 }
 
 #[derive(PartialEq, Debug, Clone, Default)]
 pub struct Filter {
     pub vec: Vec<FilterBlock>,
+    /// `$name = 255 190 0` definitions, each holding its values.
+    pub variables: Vec<TokenAndSpan>,
+    /// `Import "part.filter"` lines, each holding its path.
+    pub imports: Vec<TokenAndSpan>,
+    /// `Mixin $name` blocks, kept apart from the blocks the game sees.
+    pub mixins: Vec<Mixin>,
 }
 impl Filter {
+    /// The blocks the game sees followed by the `Mixin` blocks, for lookups
+    /// by position that must also work inside a mixin.
+    fn all_blocks(&self) -> impl Iterator<Item = &FilterBlock> {
+        self.vec.iter().chain(self.mixins.iter().map(|m| &m.block))
+    }
     pub fn search_bytes(&self, byte: usize) -> Option<Token> {
-        for block in self.vec.iter() {
+        for block in self.all_blocks() {
             if block.bspan.start <= byte && block.bspan.end >= byte {
                 return block.block.clone();
             } else {
@@ -352,16 +526,35 @@ impl Filter {
         }
         None
     }
+    /// The value at `byte` together with the keyword line it belongs to.
+    pub fn search_value(&self, byte: usize) -> Option<(&TokenAndSpan, &ValueAndSpan)> {
+        self.all_blocks()
+            .flat_map(|block| block.keywords.iter())
+            .find_map(|line| {
+                line.value
+                    .iter()
+                    .find(|value| value.span.start <= byte && value.span.end >= byte)
+                    .map(|value| (line, value))
+            })
+    }
+    /// The block, or `Mixin` block, starting last at or before `byte`.
     pub fn search_block(&self, byte: usize) -> Option<&FilterBlock> {
-        for block in self.vec.iter().rev() {
-            if block.bspan.start <= byte {
-                return Some(block);
-            }
-        }
-        None
+        self.all_blocks()
+            .filter(|block| block.bspan.start <= byte)
+            .max_by_key(|block| block.bspan.start)
     }
 }
 
+/// A `Mixin $name` block. Blocks use it with a `$name` line of its own,
+/// which the parser keeps as a keyword holding the name.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Mixin {
+    pub name: String,
+    /// The span of `$name` after `Mixin`.
+    pub span: std::ops::Range<usize>,
+    pub block: FilterBlock,
+}
+
 #[derive(PartialEq, Debug, Clone)]
 pub struct FilterBlock {
     pub block: Option<Token>,
@@ -378,6 +571,147 @@ impl FilterBlock {
         }
         None
     }
+
+    /// The comment after the `Show`/`Hide` keyword, like `$tier->t1 $type->currency`,
+    /// which the parser itself skips.
+    pub fn comment<'a>(&self, text: &'a str) -> Option<&'a str> {
+        let rest = text.get(self.bspan.end..)?;
+        let line = &rest[..rest.find('\n').unwrap_or(rest.len())];
+        line.trim_start()
+            .strip_prefix('#')
+            .map(|c| c.trim_end_matches('\r').trim())
+    }
+
+    /// The `$name->value` tags in the header comment, by name.
+    pub fn tag<'a>(&self, text: &'a str, name: &str) -> Option<&'a str> {
+        self.tags(text)
+            .into_iter()
+            .find(|(n, _, _)| *n == name)
+            .map(|(_, value, _)| value)
+    }
+
+    /// Every `$name->value` tag in the header comment, with the span of its value.
+    pub fn tags<'a>(&self, text: &'a str) -> Vec<(&'a str, &'a str, std::ops::Range<usize>)> {
+        let comment = match self.comment(text) {
+            Some(comment) => comment,
+            None => return vec![],
+        };
+        let offset = self.bspan.end + text[self.bspan.end..].find(comment).unwrap_or(0);
+        let mut at = 0;
+        let mut tags = vec![];
+        for word in comment.split_whitespace() {
+            let start = at + comment[at..].find(word).unwrap_or(0);
+            at = start + word.len();
+            if let Some((name, value)) = word.strip_prefix('$').and_then(|w| w.split_once("->")) {
+                let start = offset + start + name.len() + 3;
+                tags.push((name, value, start..start + value.len()));
+            }
+        }
+        tags
+    }
+
+    /// The end of the block's last line.
+    pub fn end(&self) -> usize {
+        self.keywords
+            .iter()
+            .flat_map(|line| {
+                line.value
+                    .last()
+                    .map(|v| v.span.end)
+                    .or(Some(line.span.end))
+            })
+            .max()
+            .unwrap_or(self.bspan.end)
+    }
+
+    /// Edits that set each action line, like `SetFontSize 30`, replacing the
+    /// block's own line or adding one after its last line.
+    pub fn set_actions(
+        &self,
+        text: &str,
+        actions: &[(Token, String)],
+    ) -> Vec<(std::ops::Range<usize>, String)> {
+        let mut edits = vec![];
+        let mut added = String::new();
+        for (token, values) in actions {
+            let line = format!("{} {}", token.label(), values)
+                .trim_end()
+                .to_string();
+            match self.keywords.iter().find(|k| k.token == *token) {
+                Some(keyword) => {
+                    let end = keyword
+                        .value
+                        .last()
+                        .map_or(keyword.span.end, |v| v.span.end);
+                    edits.push((keyword.span.start..end, line));
+                }
+                None => {
+                    let indent = self
+                        .keywords
+                        .last()
+                        .map(|k| {
+                            let start = text[..k.span.start].rfind('\n').map_or(0, |i| i + 1);
+                            &text[start..k.span.start]
+                        })
+                        .unwrap_or("\t");
+                    added.push_str(&format!("\n{}{}", indent, line));
+                }
+            }
+        }
+        if !added.is_empty() {
+            edits.push((self.end()..self.end(), added));
+        }
+        edits
+    }
+
+    /// The base items this block can still match, going by its `Class`,
+    /// `DropLevel` and `ItemLevel` conditions.
+    pub fn possible_bases<'a>(&self, poe_data: &'a PoeData) -> Vec<&'a BaseItem> {
+        self.possible_among(poe_data.bases().items(), poe_data)
+    }
+
+    /// Those of `bases` the block can still match, as with `possible_bases`.
+    pub fn possible_among<'a>(
+        &self,
+        bases: impl IntoIterator<Item = &'a BaseItem>,
+        poe_data: &PoeData,
+    ) -> Vec<&'a BaseItem> {
+        // Each `Class` line as the ids of the classes it matches.
+        let classes = self
+            .keywords
+            .iter()
+            .filter(|line| line.token == Token::Class)
+            .map(|line| {
+                let exact = line.operator() == Some("==");
+                line.strings()
+                    .into_iter()
+                    .flat_map(|s| poe_data.classes().matching(s, exact))
+                    .map(|class| class.id)
+                    .collect::<HashSet<_>>()
+            })
+            .collect::<Vec<_>>();
+        bases
+            .into_iter()
+            .filter(|base| {
+                classes
+                    .iter()
+                    .all(|ids| base.class.is_some_and(|id| ids.contains(&id)))
+            })
+            .filter(|base| {
+                self.keywords.iter().all(|line| match line.token {
+                    Token::DropLevel => match base.drop_level {
+                        Some(level) => line.matches_number(level),
+                        None => true,
+                    },
+                    Token::ItemLevel => match base.drop_level {
+                        Some(level) => line.matches_range(level, 100),
+                        None => true,
+                    },
+                    _ => true,
+                })
+            })
+            .collect()
+    }
 }
 impl Default for FilterBlock {
     fn default() -> FilterBlock {
@@ -396,6 +730,107 @@ pub struct TokenAndSpan {
     pub span: std::ops::Range<usize>,
     pub value: Vec<ValueAndSpan>,
 }
+impl TokenAndSpan {
+    pub fn operator(&self) -> Option<&str> {
+        self.value.iter().find_map(|v| match &v.token {
+            Token::Operator(s) => Some(s.as_str()),
+            _ => None,
+        })
+    }
+
+    /// The values after the keyword with the operator left out and quotes removed.
+    pub fn strings(&self) -> Vec<&str> {
+        self.value
+            .iter()
+            .filter(|v| !matches!(v.token, Token::Operator(_)))
+            .map(|v| v.value.trim_matches('"'))
+            .collect()
+    }
+
+    /// The RGBA colour of a `SetTextColor`-style line, alpha defaulting to 255.
+    pub fn color(&self) -> Option<[u8; 4]> {
+        let numbers = self
+            .strings()
+            .iter()
+            .map(|s| s.parse::<u8>().ok())
+            .collect::<Option<Vec<_>>>()?;
+        match numbers[..] {
+            [r, g, b] => Some([r, g, b, 255]),
+            [r, g, b, a] => Some([r, g, b, a]),
+            _ => None,
+        }
+    }
+
+    pub fn number(&self) -> Option<i32> {
+        self.value
+            .iter()
+            .find(|v| !matches!(v.token, Token::Operator(_)))?
+            .value
+            .trim_matches('"')
+            .parse()
+            .ok()
+    }
+
+    /// Whether `text` matches one of the values the way the game does: by
+    /// substring, or exactly when the line uses `==`.
+    pub fn matches_text(&self, text: &str) -> bool {
+        let exact = self.operator() == Some("==");
+        self.strings()
+            .iter()
+            .any(|s| if exact { text == *s } else { text.contains(s) })
+    }
+
+    /// Whether `number` satisfies the comparison on this line, like `DropLevel > 50`.
+    pub fn matches_number(&self, number: i32) -> bool {
+        match self.number() {
+            Some(target) => compare(number, self.operator().unwrap_or("="), target),
+            None => true,
+        }
+    }
+
+    /// Whether some number from `min` to `max` satisfies the comparison on
+    /// this line, like an `ItemLevel > 80` for bases dropping from level 60.
+    pub fn matches_range(&self, min: i32, max: i32) -> bool {
+        let target = match self.number() {
+            Some(target) => target,
+            None => return true,
+        };
+        if min > max {
+            return false;
+        }
+        match self.operator().unwrap_or("=") {
+            "<" => min < target,
+            "<=" => min <= target,
+            ">" => max > target,
+            ">=" => max >= target,
+            "!" | "!=" => min != max || min != target,
+            _ => min <= target && target <= max,
+        }
+    }
+}
+
+/// Replaces each span of `text`, keeping everything between them untouched.
+/// The spans must not overlap.
+pub fn apply_edits(text: &str, mut edits: Vec<(std::ops::Range<usize>, String)>) -> String {
+    let mut out = text.to_string();
+    edits.sort_by_key(|(span, _)| span.start);
+    for (span, replacement) in edits.into_iter().rev() {
+        out.replace_range(span, &replacement);
+    }
+    out
+}
+
+pub fn compare(value: i32, operator: &str, target: i32) -> bool {
+    match operator {
+        "<" => value < target,
+        "<=" => value <= target,
+        ">" => value > target,
+        ">=" => value >= target,
+        "!" | "!=" => value != target,
+        _ => value == target,
+    }
+}
+
 #[derive(PartialEq, Debug, Clone)]
 pub struct ValueAndSpan {
     pub token: Token,
@@ -416,7 +851,11 @@ fn match_filter(
             }
             KeywordType::Conditions => add_keyword(token, span, block),
             KeywordType::Actions => add_keyword(token, span, block),
-            KeywordType::Operations => {}
+            KeywordType::Operations => {
+                if let Token::Operator(s) = token.clone() {
+                    add_values(token, span, block, s);
+                }
+            }
             KeywordType::Values(s) => {
                 add_values(token, span.clone(), block, s);
             }
@@ -427,21 +866,94 @@ fn match_filter(
 pub fn parse(filter_file: &str) -> Filter {
     let mut filter = Filter::default();
     let mut block = FilterBlock::default();
-    let mut lex = Token::lexer(filter_file).spanned();
-    while let Some((token, span)) = lex.next() {
-        match_filter(&mut filter, token.clone(), span.clone(), &mut block);
+    let mut line_start = true;
+    let mut directive = None;
+    let lex = Token::lexer(filter_file).spanned();
+    for (token, span) in lex {
+        match (&token, token.keyword_type()) {
+            (Token::EndLine, _) => {
+                end_directive(&mut filter, &directive, &mut block);
+                directive = None
+            }
+            (Token::Variable(_), _)
+                if !line_start
+                    && block.block == Some(Token::Mixin)
+                    && block.keywords.is_empty() =>
+            {
+                add_keyword(token.clone(), span.clone(), &mut block)
+            }
+            (Token::Variable(_), _) | (Token::Import, _) if line_start => {
+                let line = TokenAndSpan {
+                    token: token.clone(),
+                    span: span.clone(),
+                    value: vec![],
+                };
+                if token == Token::Import {
+                    filter.imports.push(line);
+                } else {
+                    filter.variables.push(line);
+                }
+                directive = Some(token.clone());
+            }
+            (_, Some(KeywordType::Values(value))) if directive.is_some() => {
+                add_directive_value(&mut filter, &directive, token.clone(), span.clone(), value)
+            }
+            (Token::Operator(value), _) if directive.is_some() => add_directive_value(
+                &mut filter,
+                &directive,
+                token.clone(),
+                span.clone(),
+                value.clone(),
+            ),
+            _ => match_filter(&mut filter, token.clone(), span.clone(), &mut block),
+        }
+        // Tabs are not skipped by the lexer, so indentation keeps the line start.
+        line_start = token == Token::EndLine || (line_start && filter_file[span].trim().is_empty());
     }
+    end_directive(&mut filter, &directive, &mut block);
     filter.vec.push(block.clone());
+    let (mixins, blocks) = filter
+        .vec
+        .drain(..)
+        .partition::<Vec<_>, _>(|b| b.block == Some(Token::Mixin));
+    filter.vec = blocks;
+    filter.mixins = mixins
+        .into_iter()
+        .filter_map(|mut block| {
+            if !matches!(block.keywords.first()?.token, Token::Variable(_)) {
+                return None;
+            }
+            let name = block.keywords.remove(0);
+            Some(Mixin {
+                name: name.token.label(),
+                span: name.span,
+                block,
+            })
+        })
+        .collect();
     filter
 }
 
+/// A `$name` line with nothing after it uses a mixin rather than defining a
+/// variable, so it moves into the block as a keyword.
+fn end_directive(filter: &mut Filter, directive: &Option<Token>, block: &mut FilterBlock) {
+    if !matches!(directive, Some(Token::Variable(_))) {
+        return;
+    }
+    if filter.variables.last().is_some_and(|v| v.value.is_empty()) {
+        if let Some(line) = filter.variables.pop() {
+            block.keywords.push(line);
+        }
+    }
+}
+
 fn new_block(
     filter: &mut Filter,
     token: Token,
     span: std::ops::Range<usize>,
     block: &mut FilterBlock,
 ) {
-    if let Some(_) = &block.block {
+    if block.block.is_some() {
         filter.vec.push(block.clone());
     }
     block.block = Some(token.clone());
@@ -452,35 +964,52 @@ fn new_block(
 fn add_keyword(token: Token, span: std::ops::Range<usize>, block: &mut FilterBlock) {
     block.keywords.push(TokenAndSpan {
         token: token.clone(),
-        span: span,
+        span,
         value: vec![],
     })
 }
 
+/// Adds a value to the variable definition or import the line started with.
+fn add_directive_value(
+    filter: &mut Filter,
+    directive: &Option<Token>,
+    token: Token,
+    span: std::ops::Range<usize>,
+    string: String,
+) {
+    let line = match directive {
+        Some(Token::Import) => filter.imports.last_mut(),
+        _ => filter.variables.last_mut(),
+    };
+    if let Some(line) = line {
+        line.value.push(ValueAndSpan {
+            token,
+            span,
+            value: string,
+        });
+    }
+}
+
 fn add_values(token: Token, span: std::ops::Range<usize>, block: &mut FilterBlock, string: String) {
     if let Some(last_key) = block.keywords.last_mut() {
         last_key.value.push(ValueAndSpan {
             token: token.clone(),
-            span: span,
+            span,
             value: string,
         });
     };
 }
 
+/// Skips the rest of the line. Bumping past it rather than lexing it keeps a
+/// second `#`, as in `#Show # $tier->t1`, from swallowing the next line too.
 pub fn ignore_comments(lex: &mut Lexer<Token>) {
-    if lex.slice() == "#" {
-        loop {
-            match lex.next() {
-                Some(Token::EndLine) => break,
-                _ => {}
-            }
-        }
-    }
+    let rest = lex.remainder();
+    lex.bump(rest.find('\n').unwrap_or(rest.len()));
 }
 
-enum Arguments {
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum Arguments {
     Operator,
-    Value,
     ValidNumeric,
     ValidRarity,
     ValidClass,
@@ -505,10 +1034,22 @@ enum Arguments {
     CustomSound,
     PlayEffect,
     MinimapIcon,
+    Red,
+    Green,
+    Blue,
+    Alpha,
+    SoundId,
+    Volume,
+    EffectColor,
+    Temp,
+    IconSize,
+    IconColor,
+    IconShape,
+    QualityType,
 }
 
 impl Arguments {
-    fn description(&self) -> String {
+    pub fn description(&self) -> String {
         match self {
             Arguments::Operator => String::from("The following Operators can be used with numeric conditions. Note that for equal operations there is no operator required.  \n
     <   Less than
@@ -516,7 +1057,6 @@ impl Arguments {
     >   Greater than
     >=  Greater or equal to
     =   Equal to"),
-            Arguments::Value => String::new(),
             Arguments::ValidNumeric => String::from("Valid Values: Numeric Level (0-100)"),
             Arguments::ValidClass => String::from("Valid Values: Full or partial item class name"),
             Arguments::ValidRarity => String::from("Valid Values: Normal, Magic, Rare, Unique"),
@@ -540,7 +1080,122 @@ impl Arguments {
             Arguments::CustomSound => String::from("Valid Values: local path to the file (with quotation marks):  \n - \"None\" (disable)  \n - [reative path]  \n - [absolute path]"),
             Arguments::AlertSound => String::from("Valid Values:  \nDisable: None  \nId: [1-16] Ingame Sounds  \nVolume: [0-300]"),
             Arguments::PlayEffect => String::from("Valid Values:  \nDisable: None  \nColor: Red, Green, Blue, Brown, White, Yellow, Cyan, Grey, Orange, Pink, Purple  \nBeamVisualization [Temp]: Empty (no value, instantly) or Temp (temporary)"),
-            Arguments::MinimapIcon => String::from("Valid Values:\n\nDisable: -1  \nSize: 0 (large), 1 (medium), 2 (small)  \nColor: Red, Green, Blue, Brown, White, Yellow, Cyan, Grey, Orange, Pink, Purple  \nShape: Circle, Diamond, Hexagon, Square, Star, Triangle, Cross, Moon, Raindrop, Kite, Pentagon, UpsideDownHouse")
+            Arguments::MinimapIcon => String::from("Valid Values:\n\nDisable: -1  \nSize: 0 (large), 1 (medium), 2 (small)  \nColor: Red, Green, Blue, Brown, White, Yellow, Cyan, Grey, Orange, Pink, Purple  \nShape: Circle, Diamond, Hexagon, Square, Star, Triangle, Cross, Moon, Raindrop, Kite, Pentagon, UpsideDownHouse"),
+            Arguments::Red | Arguments::Green | Arguments::Blue | Arguments::Alpha => Arguments::RGB.description(),
+            Arguments::SoundId => String::from("Valid Values:  \nDisable: None  \nId: [1-16] Ingame Sounds"),
+            Arguments::Volume => String::from("Valid Values: 0-300"),
+            Arguments::EffectColor => String::from("Valid Values:  \nDisable: None  \nColor: Red, Green, Blue, Brown, White, Yellow, Cyan, Grey, Orange, Pink, Purple"),
+            Arguments::Temp => String::from("Valid Values: Empty (no value, instantly) or Temp (temporary)"),
+            Arguments::IconSize => String::from("Valid Values:  \nDisable: -1  \nSize: 0 (large), 1 (medium), 2 (small)"),
+            Arguments::IconColor => String::from("Valid Values: Red, Green, Blue, Brown, White, Yellow, Cyan, Grey, Orange, Pink, Purple"),
+            Arguments::QualityType => String::from("Valid Values: Superior, Anomalous, Divergent, Phantasmal"),
+            Arguments::IconShape => String::from("Valid Values: Circle, Diamond, Hexagon, Square, Star, Triangle, Cross, Moon, Raindrop, Kite, Pentagon, UpsideDownHouse"),
+        }
+    }
+
+    /// Short name of the argument as written in the keyword headers, like `<Red>` or `[Alpha]`.
+    pub fn label(&self) -> &'static str {
+        match self {
+            Arguments::Operator => "[Operator]",
+            Arguments::ValidNumeric => "<Level>",
+            Arguments::ValidRarity => "<Rarity>",
+            Arguments::ValidClass => "<Class>",
+            Arguments::ValidQuality => "<Quality>",
+            Arguments::ValidItem
+            | Arguments::ValidProphecy
+            | Arguments::HasInfluence
+            | Arguments::QualityType => "<Type>",
+            Arguments::ValidLinks => "<Links>",
+            Arguments::ValidLSockets | Arguments::ValidSockets => "<GroupSyntax>",
+            Arguments::Boolean => "<Boolean>",
+            Arguments::ValidHeight
+            | Arguments::ValidWidth
+            | Arguments::Enchant
+            | Arguments::StackSize
+            | Arguments::GemLevel
+            | Arguments::CorruptedMods
+            | Arguments::MapTier
+            | Arguments::RGB => "<Value>",
+            Arguments::FontSize => "<FontSize>",
+            Arguments::AlertSound => "<Id> [Volume]",
+            Arguments::CustomSound => "<FileName | FileFullPath>",
+            Arguments::PlayEffect => "<Color> [Temp]",
+            Arguments::MinimapIcon => "<Size> <Color> <Shape>",
+            Arguments::Red => "<Red>",
+            Arguments::Green => "<Green>",
+            Arguments::Blue => "<Blue>",
+            Arguments::Alpha => "[Alpha]",
+            Arguments::SoundId => "<Id>",
+            Arguments::Volume => "[Volume]",
+            Arguments::EffectColor | Arguments::IconColor => "<Color>",
+            Arguments::Temp => "[Temp]",
+            Arguments::IconSize => "<Size>",
+            Arguments::IconShape => "<Shape>",
+        }
+    }
+
+    /// Whether the argument can be given several times, like `Rarity Normal Magic`.
+    pub fn repeats(&self) -> bool {
+        matches!(
+            self,
+            Arguments::ValidRarity
+                | Arguments::ValidClass
+                | Arguments::ValidItem
+                | Arguments::ValidProphecy
+                | Arguments::ValidLSockets
+                | Arguments::ValidSockets
+                | Arguments::Enchant
+                | Arguments::HasInfluence
+                | Arguments::QualityType
+        )
+    }
+
+    /// The fixed set of values the argument accepts, empty when it is free-form.
+    pub fn values(&self) -> Vec<Token> {
+        match self {
+            Arguments::Operator => KeywordType::Operations.token_list(),
+            Arguments::Boolean => BOOLEANS
+                .iter()
+                .map(|b| Token::Boolean(b.to_string()))
+                .collect(),
+            Arguments::ValidRarity => texts(RARITIES),
+            Arguments::HasInfluence => texts(INFLUENCES),
+            Arguments::QualityType => texts(QUALITY_TYPES),
+            Arguments::EffectColor | Arguments::IconColor => texts(COLORS),
+            Arguments::Temp => texts(&["Temp"]),
+            Arguments::IconSize => ICON_SIZES
+                .iter()
+                .map(|n| Token::Numbers(n.to_string()))
+                .collect(),
+            Arguments::IconShape => texts(ICON_SHAPES),
+            _ => vec![],
+        }
+    }
+
+    /// Snippet placeholder for tab stop `n`, `None` for arguments left out of snippets.
+    pub fn placeholder(&self, n: usize) -> Option<String> {
+        let choice = |values: &[&str]| Some(format!("${{{}|{}|}}", n, values.join(",")));
+        match self {
+            Arguments::Operator | Arguments::Temp => None,
+            Arguments::Red | Arguments::Green | Arguments::Blue | Arguments::Alpha => {
+                Some(format!("${{{}:255}}", n))
+            }
+            Arguments::FontSize => Some(format!("${{{}:32}}", n)),
+            Arguments::SoundId => Some(format!("${{{}:1}}", n)),
+            Arguments::Volume => Some(format!("${{{}:300}}", n)),
+            Arguments::ValidClass
+            | Arguments::ValidItem
+            | Arguments::ValidProphecy
+            | Arguments::Enchant
+            | Arguments::CustomSound => Some(format!("\"${}\"", n)),
+            Arguments::Boolean => choice(BOOLEANS),
+            Arguments::ValidRarity => choice(RARITIES),
+            Arguments::HasInfluence => choice(INFLUENCES),
+            Arguments::QualityType => choice(QUALITY_TYPES),
+            Arguments::EffectColor | Arguments::IconColor => choice(COLORS),
+            Arguments::IconSize => choice(ICON_SIZES),
+            Arguments::IconShape => choice(ICON_SHAPES),
+            _ => Some(format!("${}", n)),
         }
     }
 }
@@ -552,6 +1207,8 @@ impl Token {
             Token::Show => String::from("# Show\n\nIf all conditions are matched, show the item and do any actions specified."),
             Token::Hide => String::from("# Hide\n\nIf all conditions are matched, hide the item and do any actions specified."),
             Token::Continue => String::from("# Continue\n\nContinues block to other match.\n\nThis is a special flag that indicates that the filter rule matching should not stop when an item matches this block.\n\nNote that if an item matches a Hide block that Continues, then later matches a Show block, it will use the most recently matched Show or Hide flag, and thus show. If an item matches and Continues and then never matches any further blocks, it will also show or hide based on the most recently matched block."),
+            Token::Import => String::from("# Import \"path\"\n\nReplaced by the filter at the path, relative to this file, when the filter is built."),
+            Token::Mixin => String::from("# Mixin $name\n\nA group of conditions and actions defined once. A block pulls them in with a `$name` line, and they are copied into it when the filter is built."),
             Token::Hash => String::from("Comment"),
             Token::Skip => String::from("Skipped Token"),
             Token::EndLine => String::from("Endline"),
@@ -573,6 +1230,7 @@ impl Token {
             Token::HasEnchantment => format!("# HasEnchantment <Value>\n\nFilter by enchantments\n\n{}", Arguments::Enchant.description()),
             Token::StackSize => format!("# StackSize [Operator] <Value>\n\nCurrency stack size\n\n{}\n\n{}",Arguments::Operator.description(), Arguments::StackSize.description()),
             Token::GemLevel => format!("# GemLevel [Operator] <Value>\n\nGem Level\n\n{}\n\n{}", Arguments::Operator.description(), Arguments::GemLevel.description()),
+            Token::GemQualityType => format!("# GemQualityType <Type>\n\nThe [alternate quality](https://pathofexile.gamepedia.com/Gem#Alternate_quality) type of a gem.\n\n{}", Arguments::QualityType.description()),
             Token::Identified => format!("# Identified <Boolean>\n\nIf an item is identified or not.\n\n{}",Arguments::Boolean.description()),
             Token::Corrupted => format!("# Corrupted <Boolean>\n\nIf an item is [corrupted](https://pathofexile.gamepedia.com/Corrupted) or not.\n\n{}", Arguments::Boolean.description()),
             Token::CorruptedMods => format!("# CorruptedMods [Operator] <Value>\n\nHow many corrupted mods are present.\n\n{}\n\n{}", Arguments::Operator.description(),Arguments::CorruptedMods.description()),
@@ -590,13 +1248,15 @@ impl Token {
             Token::SetFontSize => format!("# SetFontSize <FontSize>\n\nSets the font-size of item text.\n\n{}",Arguments::FontSize.description()),
             Token::PlayAlertSound => format!("# PlayAlertSound <Id> [Volume]\n\nPlays the specified Alert Sound with optional volume when dropped. Only one sound can be played at a time.\n\n{}", Arguments::AlertSound.description()),
             Token::PlayAlertSoundPositional => format!("# PlayAlertSoundPositional <Id> [Volume]\n\nWork as PlayAlertSound with Sound Volume relative to distance where Item dropped. Could be usable with low Tier Items to smooth Sounds.\n\n{}", Arguments::AlertSound.description()),
-            Token::DisableDropSound => format!("# DisableDropSound\n\nDisable the drop sound (undocumented feature)."),
+            Token::DisableDropSound => String::from("# DisableDropSound\n\nDisable the drop sound (undocumented feature)."),
             Token::CustomAlertSound => format!("# CustomAlertSound  \n## <FileName | FileFullPath>\n\nPlays the specified custom sound when a specified item drops. (almost all of the common file extensions should be supported)\n\n{}", Arguments::CustomSound.description()),
             Token::PlayEffect => format!("# PlayEffect <Color> [Temp]\n\nDisplays a coloured beam of light above an item highlighted by an item filter.\n\nUse the Temp parameter to have a beam only appear as the item drops.\n\nOtherwise, it will be permanently visible.\n\n{}", Arguments::PlayEffect.description()),
             Token::MinimapIcon => format!("# MinimapIcon  \n## <Size> <Color> <Shape>\n\nDisplays an icon on the minimap for specified items.\n\n{}", Arguments::MinimapIcon.description()),
-            Token::Numbers(_) => format!("# Numeric\n\nLike: 20. No quotation marks.\n\nValid Values: -1,[0-9]"),
-            Token::Quotes(_) | Token::Text(_) => format!("# String\n\nText with quotation marks, but not in all conditions, as example is Class or BaseType where values could be strings separated by space only\n\nValid Values: [a-zA-Z]"),
-            Token::Boolean(_) => format!("# Boolean\n\nTrue or False"),
+            Token::Numbers(_) => String::from("# Numeric\n\nLike: 20. No quotation marks.\n\nValid Values: -1,[0-9]"),
+            Token::Quotes(_) | Token::Text(_) => String::from("# String\n\nText with quotation marks, but not in all conditions, as example is Class or BaseType where values could be strings separated by space only\n\nValid Values: [a-zA-Z]"),
+            Token::Boolean(_) => String::from("# Boolean\n\nTrue or False"),
+            Token::Operator(_) => format!("# Operator\n\n{}", Arguments::Operator.description()),
+            Token::Variable(_) => String::from("# Variable\n\nA value list defined once with `$name = ...` and replaced by its definition when the filter is built."),
         }
     }
     // pub fn small_description(&self) -> String {
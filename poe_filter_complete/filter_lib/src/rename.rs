@@ -0,0 +1,113 @@
+use crate::hover::{byte_pos_in_string, position_in_string};
+use crate::mentions::{mention_at, mentions, Mention};
+use crate::mode_parsing::Filter;
+use lsp_types::*;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// The part of a mention a rename replaces: a name without its quotes, or
+/// the value of a tag. Colours cannot be renamed.
+fn renamed(
+    mention: &Mention,
+    span: std::ops::Range<usize>,
+    text: &str,
+) -> Option<std::ops::Range<usize>> {
+    match mention {
+        Mention::Name(..) if text[span.clone()].starts_with('"') => {
+            Some(span.start + 1..span.end - 1)
+        }
+        Mention::Name(..) | Mention::Tag(..) => Some(span),
+        Mention::Color(_) => None,
+    }
+}
+
+fn range(span: &std::ops::Range<usize>, text: &str) -> Range {
+    Range::new(
+        position_in_string(span.start, text),
+        position_in_string(span.end, text),
+    )
+}
+
+fn byte_at(position: Position, text: &str) -> usize {
+    byte_pos_in_string(
+        position.line as usize,
+        position.character as usize,
+        text.to_string(),
+    )
+}
+
+/// The base name or tag value under the cursor, if it can be renamed.
+pub fn prepare_rename(
+    params: TextDocumentPositionParams,
+    filter: &Filter,
+    text: &str,
+) -> Option<PrepareRenameResponse> {
+    let (mention, span) = mention_at(filter, text, byte_at(params.position, text))?;
+    let span = renamed(&mention, span, text)?;
+    Some(PrepareRenameResponse::RangeWithPlaceholder {
+        range: range(&span, text),
+        placeholder: text[span].to_string(),
+    })
+}
+
+/// Renames the base name or tag value under the cursor here and in `others`,
+/// touching only the values that mention it. `None` when there is nothing
+/// to rename; an error when the new name would not parse, like a tag with a
+/// space.
+pub fn rename<'a>(
+    params: RenameParams,
+    filter: &Filter,
+    text: &str,
+    others: impl IntoIterator<Item = (&'a Path, &'a Filter, &'a str)>,
+) -> Result<Option<WorkspaceEdit>, String> {
+    let position = params.text_document_position;
+    let mention = match mention_at(filter, text, byte_at(position.position, text)) {
+        Some((Mention::Color(_), _)) | None => return Ok(None),
+        Some((mention, _)) => mention,
+    };
+    let new_name = params.new_name;
+    match mention {
+        Mention::Name(..) if new_name.is_empty() || new_name.contains('"') => {
+            return Err(format!("\"{}\" is not a valid name", new_name));
+        }
+        Mention::Tag(..) if new_name.is_empty() || new_name.contains(char::is_whitespace) => {
+            return Err(format!(
+                "\"{}\" is not a valid tag value, it cannot be empty or have spaces",
+                new_name
+            ));
+        }
+        _ => {}
+    }
+    let edits = |filter: &Filter, text: &str| {
+        mentions(filter, text, &mention)
+            .into_iter()
+            // A partial name like `"Orb"` matches more than this item, so it stays.
+            .filter(|span| match &mention {
+                Mention::Name(_, name) => text[span.clone()].trim_matches('"') == name,
+                _ => true,
+            })
+            .filter_map(|span| {
+                let unquoted =
+                    matches!(mention, Mention::Name(..)) && !text[span.clone()].starts_with('"');
+                let new_text = if unquoted && new_name.contains(' ') {
+                    format!("\"{}\"", new_name)
+                } else {
+                    new_name.clone()
+                };
+                Some(TextEdit::new(
+                    range(&renamed(&mention, span, text)?, text),
+                    new_text,
+                ))
+            })
+            .collect::<Vec<_>>()
+    };
+    let mut changes = HashMap::new();
+    changes.insert(position.text_document.uri, edits(filter, text));
+    for (path, filter, text) in others {
+        let edits = edits(filter, text);
+        if let (false, Ok(uri)) = (edits.is_empty(), Url::from_file_path(path)) {
+            changes.insert(uri, edits);
+        }
+    }
+    Ok(Some(WorkspaceEdit::new(changes)))
+}
@@ -0,0 +1,58 @@
+use crate::completion;
+use crate::mode_parsing::Arguments;
+use lsp_types::*;
+
+/// Signature help at the cursor in `text`, the document as the editor has it.
+pub fn signature_help(params: SignatureHelpParams, text: &str) -> Option<SignatureHelp> {
+    let position = params.text_document_position_params.position;
+    let context =
+        completion::argument_context(text, position.line as usize, position.character as usize)?;
+    keyword_signature(&context)
+}
+
+/// Signature of a keyword with several arguments, with the argument under
+/// the cursor as the active parameter.
+pub fn keyword_signature(context: &completion::ArgumentContext) -> Option<SignatureHelp> {
+    let arguments = context.keyword.arguments();
+    if arguments.len() < 2 {
+        return None;
+    }
+
+    let mut label = context.keyword.label();
+    let mut parameters = vec![];
+    for argument in arguments.iter() {
+        label.push(' ');
+        let start = label.len() as u64;
+        label.push_str(argument.label());
+        parameters.push(ParameterInformation {
+            label: ParameterLabel::LabelOffsets([start, label.len() as u64]),
+            documentation: Some(Documentation::MarkupContent(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: argument.description(),
+            })),
+        });
+    }
+
+    let active = if arguments[0] == Arguments::Operator {
+        if context.index == 0 && !context.operator {
+            0
+        } else {
+            context.index + 1
+        }
+    } else {
+        context.index
+    };
+
+    Some(SignatureHelp {
+        signatures: vec![SignatureInformation {
+            label,
+            documentation: Some(Documentation::MarkupContent(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: context.keyword.description(),
+            })),
+            parameters: Some(parameters),
+        }],
+        active_signature: Some(0),
+        active_parameter: Some(active.min(arguments.len() - 1) as i64),
+    })
+}
@@ -0,0 +1,122 @@
+use crate::hover::position_in_string;
+use crate::imports::resolve;
+use crate::mode_parsing::{Filter, Token, ValueAndSpan};
+use lsp_types::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The file types the game plays for `CustomAlertSound`.
+pub const SOUND_EXTENSIONS: &[&str] = &["mp3", "wav"];
+
+fn is_sound(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|e| SOUND_EXTENSIONS.iter().any(|s| e.eq_ignore_ascii_case(s)))
+}
+
+/// The quoted path of every `CustomAlertSound` line with where it points,
+/// relative paths taken from the filter's directory. `"None"`, which turns
+/// the sound off, is no path.
+fn sounds<'a>(path: &Path, filter: &'a Filter) -> Vec<(&'a ValueAndSpan, PathBuf)> {
+    filter
+        .lines()
+        .filter(|line| line.token == Token::CustomAlertSound)
+        .filter_map(|line| {
+            let value = line
+                .value
+                .iter()
+                .find(|v| matches!(v.token, Token::Quotes(_)))?;
+            let sound = value.value.trim_matches('"');
+            if sound.eq_ignore_ascii_case("none") {
+                return None;
+            }
+            Some((value, resolve(path, sound)))
+        })
+        .collect()
+}
+
+/// The inside of a quoted value.
+fn inner_range(value: &ValueAndSpan, text: &str) -> Range {
+    Range::new(
+        position_in_string(value.span.start + 1, text),
+        position_in_string(value.span.end - 1, text),
+    )
+}
+
+/// Links from each `CustomAlertSound` path to the sound file.
+pub fn document_links(path: &Path, filter: &Filter, text: &str) -> Vec<DocumentLink> {
+    sounds(path, filter)
+        .into_iter()
+        .filter_map(|(value, sound)| {
+            Some(DocumentLink {
+                range: inner_range(value, text),
+                target: Url::from_file_path(sound).ok()?,
+                tooltip: None,
+            })
+        })
+        .collect()
+}
+
+/// Errors for sounds the game cannot play and warnings for sound files that
+/// are not there.
+pub fn problems(path: &Path, filter: &Filter, text: &str) -> Vec<Diagnostic> {
+    sounds(path, filter)
+        .into_iter()
+        .filter_map(|(value, sound)| {
+            let (severity, message) = if !is_sound(&sound) {
+                (
+                    DiagnosticSeverity::Error,
+                    format!(
+                        "Unsupported sound file, use {}",
+                        SOUND_EXTENSIONS.join(" or ")
+                    ),
+                )
+            } else if !sound.is_file() {
+                (
+                    DiagnosticSeverity::Warning,
+                    format!("Sound file {} not found", sound.display()),
+                )
+            } else {
+                return None;
+            };
+            Some(Diagnostic {
+                range: inner_range(value, text),
+                severity: Some(severity),
+                source: Some(String::from("poe-filter")),
+                message,
+                ..Diagnostic::default()
+            })
+        })
+        .collect()
+}
+
+/// Sound files and folders for the path typed so far inside
+/// `CustomAlertSound ""`, looked up from the filter's directory.
+pub fn completion(path: &Path, typed: &str) -> Vec<CompletionItem> {
+    let folder = &typed[..typed.rfind('/').map_or(0, |i| i + 1)];
+    let entries = match fs::read_dir(resolve(path, folder)) {
+        Ok(entries) => entries,
+        Err(_) => return vec![],
+    };
+    let mut items = entries
+        .filter_map(|e| e.ok())
+        .filter_map(|entry| {
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let kind = if path.is_dir() {
+                CompletionItemKind::Folder
+            } else if is_sound(&path) {
+                CompletionItemKind::File
+            } else {
+                return None;
+            };
+            Some(CompletionItem {
+                label: name,
+                kind: Some(kind),
+                ..CompletionItem::default()
+            })
+        })
+        .filter(|item| !item.label.starts_with('.'))
+        .collect::<Vec<_>>();
+    items.sort_by(|a, b| a.label.cmp(&b.label));
+    items
+}
@@ -0,0 +1,143 @@
+use crate::mode_parsing::{apply_edits, compare, parse, FilterBlock, KeywordType, Token};
+use logos::Logos;
+
+/// The published strictness levels, loosest first; a level is its index here.
+pub const STRICTNESS: &[&str] = &[
+    "soft",
+    "regular",
+    "semi-strict",
+    "strict",
+    "very-strict",
+    "uber-strict",
+];
+
+/// What an annotation does to its block.
+#[derive(Clone, Debug, PartialEq)]
+pub enum StrictAction {
+    Show,
+    Hide,
+    /// Leaves the block out of the filter.
+    Drop,
+    /// Sets an action line like `SetFontSize 30`, adding it when missing.
+    Set(Token, String),
+}
+
+/// A `$strictness>=3 hide` annotation from a block's header comment.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Annotation {
+    pub operator: String,
+    pub level: i32,
+    pub action: StrictAction,
+}
+
+impl Annotation {
+    pub fn applies(&self, level: usize) -> bool {
+        compare(level as i32, &self.operator, self.level)
+    }
+}
+
+/// The strictness annotations of a block. Each one runs up to the next
+/// `$` tag, so `# $strictness>=2 SetFontSize 35 $tier->t1` sets the font size.
+pub fn annotations(block: &FilterBlock, text: &str) -> Vec<Annotation> {
+    let comment = match block.comment(text) {
+        Some(comment) => comment,
+        None => return vec![],
+    };
+    let mut out = vec![];
+    let mut words = comment.split_whitespace().peekable();
+    while let Some(word) = words.next() {
+        let condition = match word.strip_prefix("$strictness") {
+            Some(condition) => condition,
+            None => continue,
+        };
+        let mut args = vec![];
+        while let Some(arg) = words.peek().filter(|w| !w.starts_with('$')) {
+            args.push(*arg);
+            words.next();
+        }
+        let split = condition
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(condition.len());
+        let (operator, level) = condition.split_at(split);
+        let level = match level.parse() {
+            Ok(level) => level,
+            Err(_) => continue,
+        };
+        if let Some(action) = action(&args) {
+            out.push(Annotation {
+                operator: if operator.is_empty() { "=" } else { operator }.to_string(),
+                level,
+                action,
+            });
+        }
+    }
+    out
+}
+
+fn action(args: &[&str]) -> Option<StrictAction> {
+    match args {
+        [] => None,
+        [word] if word.eq_ignore_ascii_case("show") => Some(StrictAction::Show),
+        [word] if word.eq_ignore_ascii_case("hide") => Some(StrictAction::Hide),
+        [word] if word.eq_ignore_ascii_case("drop") => Some(StrictAction::Drop),
+        [keyword, values @ ..] => {
+            let token = Token::lexer(keyword).next()?;
+            if token.keyword_type() != Some(KeywordType::Actions) {
+                return None;
+            }
+            Some(StrictAction::Set(token, values.join(" ")))
+        }
+    }
+}
+
+/// The filter at strictness `level`: annotated blocks are flipped, dropped
+/// or restyled and everything else is copied as written.
+pub fn strictness(text: &str, level: usize) -> String {
+    let filter = parse(text);
+    let mut edits = vec![];
+    for block in filter.vec.iter().filter(|b| b.block.is_some()) {
+        let annotations = annotations(block, text)
+            .into_iter()
+            .filter(|a| a.applies(level))
+            .collect::<Vec<_>>();
+        if annotations.iter().any(|a| a.action == StrictAction::Drop) {
+            let start = text[..block.bspan.start].rfind('\n').map_or(0, |i| i + 1);
+            let rest = &text[block.end()..];
+            let end = rest
+                .split_inclusive('\n')
+                .enumerate()
+                .take_while(|(i, line)| *i == 0 || line.trim().is_empty())
+                .map(|(_, line)| line.len())
+                .sum::<usize>();
+            edits.push((start..block.end() + end, String::new()));
+            continue;
+        }
+        let mut visibility = None;
+        let mut lines = Vec::<(Token, String)>::new();
+        for annotation in annotations {
+            match annotation.action {
+                StrictAction::Show => visibility = Some("Show"),
+                StrictAction::Hide => visibility = Some("Hide"),
+                StrictAction::Set(token, values) => {
+                    lines.retain(|(t, _)| *t != token);
+                    lines.push((token, values));
+                }
+                StrictAction::Drop => {}
+            }
+        }
+        if let (Some(visibility), Some(Token::Show | Token::Hide)) = (visibility, &block.block) {
+            edits.push((block.bspan.clone(), visibility.to_string()));
+        }
+        edits.extend(block.set_actions(text, &lines));
+    }
+    apply_edits(text, edits)
+}
+
+/// Every strictness level of the filter, named after `STRICTNESS`.
+pub fn variants(text: &str) -> Vec<(&'static str, String)> {
+    STRICTNESS
+        .iter()
+        .enumerate()
+        .map(|(level, name)| (*name, strictness(text, level)))
+        .collect()
+}
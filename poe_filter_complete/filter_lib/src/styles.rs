@@ -0,0 +1,202 @@
+use crate::data_parsing::DataError;
+use crate::mode_parsing::{apply_edits, parse, FilterBlock, Token};
+use crate::tiering::tier_tag;
+use logos::Logos;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// The actions a style may set; everything else stays with the block.
+pub const STYLE_ACTIONS: &[Token] = &[
+    Token::SetTextColor,
+    Token::SetBorderColor,
+    Token::SetBackgroundColor,
+    Token::SetFontSize,
+    Token::PlayAlertSound,
+    Token::PlayAlertSoundPositional,
+    Token::CustomAlertSound,
+    Token::DisableDropSound,
+    Token::MinimapIcon,
+    Token::PlayEffect,
+];
+
+/// A named set of action lines, applied to every block tagged `$style->name`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StyleEntry {
+    pub name: String,
+    pub actions: Vec<(Token, String)>,
+}
+
+/// A style file, one `[name]` section of action lines per style:
+///
+/// ```text
+/// [currency-t1]
+/// SetTextColor 255 0 0 255
+/// PlayAlertSound 1 300
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Style {
+    pub entries: Vec<StyleEntry>,
+}
+
+impl Style {
+    pub fn parse(file: &str, string: &str) -> Result<Self, DataError> {
+        let mut style = Style::default();
+        for (i, line) in string.lines().enumerate() {
+            let error = |message: String| DataError::Syntax {
+                file: file.to_string(),
+                line: i + 1,
+                message,
+            };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                style.entries.push(StyleEntry {
+                    name: name.trim().to_string(),
+                    actions: vec![],
+                });
+                continue;
+            }
+            let (keyword, values) = match line.find(char::is_whitespace) {
+                Some(at) => (&line[..at], line[at..].trim()),
+                None => (line, ""),
+            };
+            let token = Token::lexer(keyword).next().unwrap_or_default();
+            if !STYLE_ACTIONS.contains(&token) || token.label() != keyword {
+                return Err(error(format!("{} is not a style action", keyword)));
+            }
+            match style.entries.last_mut() {
+                Some(entry) => entry.actions.push((token, values.to_string())),
+                None => return Err(error("action outside of a [style] section".to_string())),
+            }
+        }
+        Ok(style)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, DataError> {
+        let string = fs::read_to_string(path).map_err(|error| DataError::Io {
+            path: path.to_path_buf(),
+            error,
+        })?;
+        let file = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Style::parse(&file, &string)
+    }
+
+    pub fn entry(&self, name: &str) -> Option<&StyleEntry> {
+        self.entries.iter().find(|e| e.name == name)
+    }
+}
+
+impl fmt::Display for Style {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, entry) in self.entries.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            writeln!(f, "[{}]", entry.name)?;
+            for (token, values) in entry.actions.iter() {
+                writeln!(f, "{} {}", token.label(), values)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The filter with the actions of every `$style->name` block taken from `style`.
+/// Blocks without a tag, or with a style the file leaves out, keep their own.
+pub fn render(text: &str, style: &Style) -> String {
+    let filter = parse(text);
+    let edits = filter
+        .vec
+        .iter()
+        .filter(|b| b.block.is_some())
+        .filter_map(|block| {
+            let entry = style.entry(block.tag(text, "style")?)?;
+            Some(block.set_actions(text, &entry.actions))
+        })
+        .flatten()
+        .collect();
+    apply_edits(text, edits)
+}
+
+/// A style file grouping the blocks with identical actions, together with
+/// the filter tagged to use it. Styles are named after a block's existing
+/// `$style`, tier or type tag, or numbered.
+pub fn extract(text: &str) -> (Style, String) {
+    let filter = parse(text);
+    let mut style = Style::default();
+    let mut edits = vec![];
+    for block in filter.vec.iter().filter(|b| b.block.is_some()) {
+        let actions = style_actions(block);
+        if actions.is_empty() {
+            continue;
+        }
+        let existing = block.tag(text, "style");
+        let name = match style.entries.iter().find(|e| e.actions == actions) {
+            Some(entry) => entry.name.clone(),
+            None => {
+                let base = existing
+                    .map(String::from)
+                    .or_else(|| tier_tag(block, text))
+                    .or_else(|| block.tag(text, "type").map(String::from))
+                    .unwrap_or_else(|| format!("style-{}", style.entries.len() + 1));
+                let mut name = base.clone();
+                let mut n = 1;
+                while style.entry(&name).is_some() {
+                    n += 1;
+                    name = format!("{}-{}", base, n);
+                }
+                style.entries.push(StyleEntry {
+                    name: name.clone(),
+                    actions,
+                });
+                name
+            }
+        };
+        if existing == Some(name.as_str()) {
+            continue;
+        }
+        let tag = format!("$style->{}", name);
+        let header_end = text[block.bspan.end..]
+            .find('\n')
+            .map_or(text.len(), |i| block.bspan.end + i);
+        let header = &text[block.bspan.end..header_end];
+        match existing {
+            Some(old) => {
+                let old = format!("$style->{}", old);
+                let at = block.bspan.end + header.find(&old).unwrap_or_default();
+                edits.push((at..at + old.len(), tag));
+            }
+            None if block.comment(text).is_some() => {
+                let end = block.bspan.end + header.trim_end().len();
+                edits.push((end..end, format!(" {}", tag)));
+            }
+            None => edits.push((block.bspan.end..block.bspan.end, format!(" # {}", tag))),
+        }
+    }
+    (style, apply_edits(text, edits))
+}
+
+/// The block's style actions in the order they are written, values
+/// separated by single spaces.
+fn style_actions(block: &FilterBlock) -> Vec<(Token, String)> {
+    block
+        .keywords
+        .iter()
+        .filter(|k| STYLE_ACTIONS.contains(&k.token))
+        .map(|k| {
+            let values = k
+                .value
+                .iter()
+                .map(|v| v.value.as_str())
+                .collect::<Vec<_>>()
+                .join(" ");
+            (k.token.clone(), values)
+        })
+        .collect()
+}
@@ -0,0 +1,142 @@
+use crate::hover::position_in_string;
+use crate::imports::Imported;
+use crate::mode_parsing::Token;
+use lsp_types::*;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// A `# [[0200]] 6 LINKS` or `# [0201] Sockets` line framed by `#===` or
+/// `#---` rules, with its byte span.
+fn banners(text: &str) -> Vec<(&str, std::ops::Range<usize>)> {
+    let mut out = vec![];
+    let mut start = 0;
+    let mut previous = "";
+    for line in text.split_inclusive('\n') {
+        let trimmed = line.trim_end();
+        let ruled = previous.starts_with("#=") || previous.starts_with("#-");
+        if let Some(title) = trimmed.strip_prefix('#').map(str::trim) {
+            if ruled && title.starts_with('[') {
+                out.push((title, start..start + trimmed.len()));
+            }
+        }
+        previous = trimmed;
+        start += line.len();
+    }
+    out
+}
+
+/// The symbols `workspace/symbol` finds in a filter: section banners, blocks
+/// by their header tags, and the bases a block lists by exact name. Blocks
+/// and bases are contained in the section above them.
+pub fn symbols(file: &Imported) -> Vec<SymbolInformation> {
+    let uri = match Url::from_file_path(&file.path) {
+        Ok(uri) => uri,
+        Err(_) => return vec![],
+    };
+    let text = file.text.as_str();
+    let symbol = |name: String, kind, span: &std::ops::Range<usize>, container: Option<&str>| {
+        SymbolInformation {
+            name,
+            kind,
+            deprecated: None,
+            location: Location::new(
+                uri.clone(),
+                Range::new(
+                    position_in_string(span.start, text),
+                    position_in_string(span.end, text),
+                ),
+            ),
+            container_name: container.map(String::from),
+        }
+    };
+    let banners = banners(text);
+    let mut out = banners
+        .iter()
+        .map(|(title, span)| symbol(title.to_string(), SymbolKind::Module, span, None))
+        .collect::<Vec<_>>();
+    for block in file.filter.vec.iter().filter(|b| b.block.is_some()) {
+        let section = banners
+            .iter()
+            .rev()
+            .find(|(_, span)| span.start < block.bspan.start)
+            .map(|(title, _)| *title);
+        let tags = block
+            .tags(text)
+            .iter()
+            .map(|(name, value, _)| format!("{}->{}", name, value))
+            .collect::<Vec<_>>()
+            .join(" ");
+        if !tags.is_empty() {
+            out.push(symbol(
+                tags.clone(),
+                SymbolKind::Object,
+                &block.bspan,
+                section,
+            ));
+        }
+        let container = if tags.is_empty() {
+            section
+        } else {
+            Some(tags.as_str())
+        };
+        let bases = block
+            .keywords
+            .iter()
+            .filter(|line| line.token == Token::BaseType && line.operator() == Some("=="))
+            .flat_map(|line| line.value.iter())
+            .filter(|v| matches!(v.token, Token::Quotes(_)));
+        for base in bases {
+            let name = base.value.trim_matches('"').to_string();
+            out.push(symbol(name, SymbolKind::Constant, &base.span, container));
+        }
+    }
+    out
+}
+
+/// The symbols of every workspace filter, kept by file so one can be
+/// replaced when it changes on disk or in the editor.
+#[derive(Clone, Debug, Default)]
+pub struct SymbolIndex {
+    files: BTreeMap<PathBuf, Vec<SymbolInformation>>,
+}
+
+impl SymbolIndex {
+    pub fn new(files: &[Imported]) -> Self {
+        let mut index = SymbolIndex::default();
+        for file in files {
+            index.update(file);
+        }
+        index
+    }
+
+    pub fn update(&mut self, file: &Imported) {
+        self.files.insert(file.path.clone(), symbols(file));
+    }
+
+    pub fn remove(&mut self, path: &Path) {
+        self.files.remove(path);
+    }
+
+    /// The symbols whose name or container holds every word of `query`,
+    /// ignoring case, so `t1 currency` finds `type->currency tier->t1`.
+    pub fn search(&self, query: &str) -> Vec<SymbolInformation> {
+        let words = query
+            .split_whitespace()
+            .map(str::to_lowercase)
+            .collect::<Vec<_>>();
+        self.files
+            .values()
+            .flatten()
+            .filter(|symbol| {
+                let haystack = format!(
+                    "{} {}",
+                    symbol.name,
+                    symbol.container_name.as_deref().unwrap_or_default()
+                )
+                .to_lowercase();
+                words.iter().all(|word| haystack.contains(word))
+            })
+            .cloned()
+            .collect()
+    }
+}
@@ -0,0 +1,222 @@
+use crate::data_parsing::DataError;
+use crate::economy::{Category, Prices};
+use crate::mode_parsing::{apply_edits, parse, FilterBlock, Token};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// One tier of a tiering config: every item of `category` worth at least
+/// `min_chaos` and less than `max_chaos` goes into the blocks tagged `tier`.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct TierRule {
+    /// The block tag, like `currency->t1` for `# $tier->currency->t1` or
+    /// `# $type->currency $tier->t1`.
+    pub tier: String,
+    pub category: String,
+    pub min_chaos: f64,
+    pub max_chaos: Option<f64>,
+    /// Prices based on fewer listings are left out of the tier.
+    pub min_count: Option<u32>,
+}
+
+/// Chaos-value thresholds for the tier blocks of a filter.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TierConfig {
+    pub rules: Vec<TierRule>,
+}
+
+impl TierConfig {
+    /// A CSV with `tier`, `category`, `min_chaos` and optional `max_chaos`
+    /// and `min_count` columns. An item goes into the first tier it fits.
+    pub fn from_csv(file: &str, string: &str) -> Result<Self, DataError> {
+        let rules = csv::Reader::from_reader(string.as_bytes())
+            .deserialize()
+            .collect::<Result<Vec<TierRule>, _>>()
+            .map_err(|error| DataError::Csv {
+                file: file.to_string(),
+                error,
+            })?;
+        Ok(TierConfig { rules })
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, DataError> {
+        let string = fs::read_to_string(path).map_err(|error| DataError::Io {
+            path: path.to_path_buf(),
+            error,
+        })?;
+        let file = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        TierConfig::from_csv(&file, &string)
+    }
+
+    /// The items of each tier, sorted by name. Uniques are tiered by base
+    /// type, going by the most valuable unique on it.
+    pub fn tiers(&self, prices: &Prices) -> BTreeMap<String, Vec<String>> {
+        let mut values = BTreeMap::<(Category, &str), (f64, u32)>::new();
+        for price in prices.prices.iter().filter(|p| p.links.is_none()) {
+            let name = match price.category {
+                Category::Unique => price.base_type.as_str(),
+                _ => price.name.as_str(),
+            };
+            let value = values
+                .entry((price.category, name))
+                .or_insert((price.chaos_value, price.count));
+            if price.chaos_value > value.0 {
+                *value = (price.chaos_value, price.count);
+            }
+        }
+
+        let mut tiers = self
+            .rules
+            .iter()
+            .map(|rule| (rule.tier.clone(), vec![]))
+            .collect::<BTreeMap<_, Vec<String>>>();
+        for ((category, name), (chaos, count)) in values {
+            let rule = self.rules.iter().find(|rule| {
+                Category::from_file_name(&rule.category) == category
+                    && chaos >= rule.min_chaos
+                    && rule.max_chaos.is_none_or(|max| chaos < max)
+                    && rule.min_count.is_none_or(|min| count >= min)
+            });
+            if let Some(rule) = rule {
+                if let Some(items) = tiers.get_mut(&rule.tier) {
+                    items.push(name.to_string());
+                }
+            }
+        }
+        for items in tiers.values_mut() {
+            items.sort();
+        }
+        tiers
+    }
+}
+
+/// An item that changed tier; `None` means it was in no tier block.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct TierChange {
+    pub item: String,
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+/// The rewritten filter together with what changed.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Tiering {
+    #[serde(skip)]
+    pub text: String,
+    pub changes: Vec<TierChange>,
+    /// Tiers left without items, whose blocks were commented out.
+    pub disabled: Vec<String>,
+    /// Tiers in the config with no tagged block holding a `BaseType` line.
+    pub missing: Vec<String>,
+}
+
+/// The tier tag of a block: `$tier->currency->t1` as is, or the `$type`
+/// and `$tier` tags joined, like `currency->t1`.
+pub fn tier_tag(block: &FilterBlock, text: &str) -> Option<String> {
+    let tier = block.tag(text, "tier")?;
+    match block.tag(text, "type") {
+        Some(kind) => Some(format!("{}->{}", kind, tier)),
+        None => Some(tier.to_string()),
+    }
+}
+
+/// Rewrites the `BaseType` line of every tier block in the config with the
+/// items the prices put in that tier, leaving the rest of the text as it is.
+pub fn retier(text: &str, config: &TierConfig, prices: &Prices) -> Tiering {
+    let tiers = config.tiers(prices);
+    let filter = parse(text);
+    let mut edits = vec![];
+    let mut before = BTreeMap::<String, String>::new();
+    let mut found = vec![];
+    let mut disabled = vec![];
+
+    for block in filter.vec.iter().filter(|b| b.block.is_some()) {
+        let tag = match tier_tag(block, text) {
+            Some(tag) => tag,
+            None => continue,
+        };
+        let items = match tiers.get(&tag) {
+            Some(items) => items,
+            None => continue,
+        };
+        let line = match block.keywords.iter().find(|l| l.token == Token::BaseType) {
+            Some(line) => line,
+            None => continue,
+        };
+        for name in line.strings() {
+            before
+                .entry(name.to_string())
+                .or_insert_with(|| tag.clone());
+        }
+        found.push(tag.clone());
+
+        if items.is_empty() {
+            let start = text[..block.bspan.start].rfind('\n').map_or(0, |i| i + 1);
+            edits.push((start..block.end(), comment_out(&text[start..block.end()])));
+            disabled.push(tag);
+            continue;
+        }
+        let values = line
+            .value
+            .iter()
+            .filter(|v| !matches!(v.token, Token::Operator(_)))
+            .collect::<Vec<_>>();
+        let list = items
+            .iter()
+            .map(|i| format!("\"{}\"", i))
+            .collect::<Vec<_>>()
+            .join(" ");
+        match (values.first(), values.last(), line.operator()) {
+            (Some(first), Some(last), Some(_)) => {
+                edits.push((first.span.start..last.span.end, list))
+            }
+            (Some(first), Some(last), None) => {
+                edits.push((first.span.start..last.span.end, format!("== {}", list)))
+            }
+            _ => edits.push((line.span.end..line.span.end, format!(" == {}", list))),
+        }
+    }
+
+    let mut after = BTreeMap::<String, String>::new();
+    for (tag, items) in tiers.iter().filter(|(tag, _)| found.contains(tag)) {
+        for item in items {
+            after.entry(item.clone()).or_insert_with(|| tag.clone());
+        }
+    }
+    let mut changes = before
+        .keys()
+        .chain(after.keys())
+        .filter(|item| before.get(*item) != after.get(*item))
+        .map(|item| TierChange {
+            item: item.clone(),
+            from: before.get(item).cloned(),
+            to: after.get(item).cloned(),
+        })
+        .collect::<Vec<_>>();
+    changes.sort();
+    changes.dedup();
+
+    disabled.sort();
+    disabled.dedup();
+    Tiering {
+        text: apply_edits(text, edits),
+        changes,
+        disabled,
+        missing: tiers
+            .keys()
+            .filter(|tag| !found.contains(tag))
+            .cloned()
+            .collect(),
+    }
+}
+
+/// Turns every line into a comment, the way disabled blocks are written.
+fn comment_out(text: &str) -> String {
+    text.split_inclusive('\n')
+        .map(|line| format!("#{}", line))
+        .collect()
+}
@@ -0,0 +1,284 @@
+use crate::hover::{byte_pos_in_string, position_in_string};
+use crate::imports::{import_at, Imported};
+use crate::mixins;
+use crate::mode_parsing::{apply_edits, Filter, Mixin, Token, TokenAndSpan, ValueAndSpan};
+use lsp_types::*;
+use std::collections::HashMap;
+
+impl Filter {
+    /// The first definition of `$name`; later ones are ignored.
+    pub fn definition(&self, name: &str) -> Option<&TokenAndSpan> {
+        self.variables
+            .iter()
+            .find(|d| matches!(&d.token, Token::Variable(n) if n == name))
+    }
+
+    /// The first `Mixin $name` block.
+    pub fn mixin(&self, name: &str) -> Option<&Mixin> {
+        self.mixins.iter().find(|m| m.name == name)
+    }
+
+    /// Where `$name` is defined, as a variable or a mixin.
+    pub fn definition_span(&self, name: &str) -> Option<&std::ops::Range<usize>> {
+        self.definition(name)
+            .map(|d| &d.span)
+            .or_else(|| self.mixin(name).map(|m| &m.span))
+    }
+
+    /// Every keyword line, in blocks and in mixins.
+    pub fn lines(&self) -> impl Iterator<Item = &TokenAndSpan> {
+        self.vec
+            .iter()
+            .chain(self.mixins.iter().map(|m| &m.block))
+            .flat_map(|block| block.keywords.iter())
+    }
+
+    /// Every use of `$name` as a value, in blocks and in other definitions.
+    pub fn usages<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a ValueAndSpan> + 'a {
+        self.lines()
+            .chain(self.variables.iter())
+            .flat_map(|line| line.value.iter())
+            .filter(move |v| matches!(&v.token, Token::Variable(n) if n == name))
+    }
+
+    /// Every `$name` line that pulls in the mixin.
+    pub fn mixin_uses<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a TokenAndSpan> + 'a {
+        self.lines()
+            .filter(move |line| matches!(&line.token, Token::Variable(n) if n == name))
+    }
+
+    /// The variable or mixin defined or used at `byte`, with its span there.
+    pub fn variable_at(&self, byte: usize) -> Option<(&str, std::ops::Range<usize>)> {
+        let in_span = |span: &std::ops::Range<usize>| span.start <= byte && span.end >= byte;
+        let definitions = self.variables.iter().map(|d| (&d.token, &d.span));
+        let uses = self
+            .lines()
+            .chain(self.variables.iter())
+            .flat_map(|line| line.value.iter())
+            .map(|v| (&v.token, &v.span))
+            .chain(self.lines().map(|line| (&line.token, &line.span)));
+        let mixins = self
+            .mixins
+            .iter()
+            .find(|m| in_span(&m.span))
+            .map(|m| (m.name.as_str(), m.span.clone()));
+        mixins.or_else(|| {
+            definitions
+                .chain(uses)
+                .find_map(|(token, span)| match token {
+                    Token::Variable(name) if in_span(span) => Some((name.as_str(), span.clone())),
+                    _ => None,
+                })
+        })
+    }
+}
+
+/// The text a definition stands for, like `255 190 0` or `"Vaal Regalia" "Astral Plate"`.
+pub fn value_text<'a>(definition: &TokenAndSpan, text: &'a str) -> &'a str {
+    let values = definition
+        .value
+        .iter()
+        .filter(|v| !matches!(v.token, Token::Operator(_)))
+        .collect::<Vec<_>>();
+    match (values.first(), values.last()) {
+        (Some(first), Some(last)) => &text[first.span.start..last.span.end],
+        _ => "",
+    }
+}
+
+/// Every variable with the variables in its value replaced, in the order
+/// they are defined; a definition can only use the ones before it.
+pub fn resolved(filter: &Filter, text: &str) -> HashMap<String, String> {
+    let mut out = HashMap::<String, String>::new();
+    for definition in filter.variables.iter() {
+        let name = match &definition.token {
+            Token::Variable(name) => name,
+            _ => continue,
+        };
+        if out.contains_key(name) {
+            continue;
+        }
+        let value = value_text(definition, text);
+        let offset = definition
+            .value
+            .iter()
+            .find(|v| !matches!(v.token, Token::Operator(_)))
+            .map_or(0, |v| v.span.start);
+        let edits = definition
+            .value
+            .iter()
+            .filter_map(|v| match &v.token {
+                Token::Variable(used) => Some((
+                    v.span.start - offset..v.span.end - offset,
+                    out.get(used)?.clone(),
+                )),
+                _ => None,
+            })
+            .collect();
+        let value = apply_edits(value, edits);
+        out.insert(name.clone(), value);
+    }
+    out
+}
+
+/// The plain filter the game loads: definition lines are removed and every
+/// use is replaced by its value. Undefined variables are left as written.
+pub fn expand(text: &str) -> String {
+    let filter = crate::mode_parsing::parse(text);
+    let values = resolved(&filter, text);
+    let mut edits = filter
+        .variables
+        .iter()
+        .map(|definition| {
+            let start = text[..definition.span.start]
+                .rfind('\n')
+                .map_or(0, |i| i + 1);
+            let end = text[definition.span.start..]
+                .find('\n')
+                .map_or(text.len(), |i| definition.span.start + i + 1);
+            (start..end, String::new())
+        })
+        .collect::<Vec<_>>();
+    for value in filter.lines().flat_map(|line| line.value.iter()) {
+        if let Token::Variable(name) = &value.token {
+            if let Some(expanded) = values.get(name) {
+                edits.push((value.span.clone(), expanded.clone()));
+            }
+        }
+    }
+    apply_edits(text, edits)
+}
+
+/// Hover text for `$name`: what it expands to, looking in imported files
+/// when it is not defined here.
+pub fn hover(filter: &Filter, name: &str, text: &str, imported: &[Imported]) -> String {
+    let mixin = filter
+        .mixin(name)
+        .map(|m| mixins::lines(m, text))
+        .or_else(|| {
+            imported
+                .iter()
+                .find_map(|i| Some(mixins::lines(i.filter.mixin(name)?, &i.text)))
+        });
+    if let Some(lines) = mixin {
+        return format!("# Mixin {}\n\n    {}", name, lines.join("\n    "));
+    }
+    let value = resolved(filter, text).get(name).cloned().or_else(|| {
+        imported
+            .iter()
+            .find_map(|i| resolved(&i.filter, &i.text).get(name).cloned())
+    });
+    match value {
+        Some(value) => format!("# Variable {}\n\n    {}", name, value),
+        None => format!("# Variable {}\n\nUndefined", name),
+    }
+}
+
+fn range(span: &std::ops::Range<usize>, text: &str) -> Range {
+    Range::new(
+        position_in_string(span.start, text),
+        position_in_string(span.end, text),
+    )
+}
+
+fn byte_at(position: Position, text: &str) -> usize {
+    byte_pos_in_string(
+        position.line as usize,
+        position.character as usize,
+        text.to_string(),
+    )
+}
+
+/// The definition of the variable or mixin under the cursor, here or in an imported
+/// file, or the file an `Import` line names.
+pub fn goto_definition(
+    params: GotoDefinitionParams,
+    filter: &Filter,
+    text: &str,
+    imported: &[Imported],
+) -> Option<GotoDefinitionResponse> {
+    let position = params.text_document_position_params;
+    let uri = position.text_document.uri;
+    let byte = byte_at(position.position, text);
+    if let Some((_, target)) = uri
+        .to_file_path()
+        .ok()
+        .and_then(|path| import_at(filter, &path, byte))
+    {
+        let target = Url::from_file_path(target).ok()?;
+        return Some(GotoDefinitionResponse::Scalar(Location::new(
+            target,
+            Range::default(),
+        )));
+    }
+    let (name, _) = filter.variable_at(byte)?;
+    if let Some(span) = filter.definition_span(name) {
+        return Some(GotoDefinitionResponse::Scalar(Location::new(
+            uri,
+            range(span, text),
+        )));
+    }
+    imported.iter().find_map(|i| {
+        let span = i.filter.definition_span(name)?;
+        Some(GotoDefinitionResponse::Scalar(Location::new(
+            Url::from_file_path(&i.path).ok()?,
+            range(span, &i.text),
+        )))
+    })
+}
+
+/// Every use of the variable or mixin under the cursor, here and in imported files.
+pub fn references(
+    params: ReferenceParams,
+    filter: &Filter,
+    text: &str,
+    imported: &[Imported],
+) -> Vec<Location> {
+    let include_declaration = params.context.include_declaration;
+    let position = params.text_document_position;
+    let uri = position.text_document.uri;
+    let name = match filter.variable_at(byte_at(position.position, text)) {
+        Some((name, _)) => name,
+        None => return vec![],
+    };
+    let mut out = locations(filter, name, text, &uri, include_declaration);
+    for i in imported {
+        if let Ok(uri) = Url::from_file_path(&i.path) {
+            out.extend(locations(
+                &i.filter,
+                name,
+                &i.text,
+                &uri,
+                include_declaration,
+            ));
+        }
+    }
+    out
+}
+
+fn locations(
+    filter: &Filter,
+    name: &str,
+    text: &str,
+    uri: &Url,
+    include_declaration: bool,
+) -> Vec<Location> {
+    let definitions = filter
+        .variables
+        .iter()
+        .filter(|d| matches!(&d.token, Token::Variable(n) if n == name))
+        .map(|d| &d.span)
+        .chain(
+            filter
+                .mixins
+                .iter()
+                .filter(|m| m.name == name)
+                .map(|m| &m.span),
+        )
+        .filter(|_| include_declaration);
+    definitions
+        .chain(filter.usages(name).map(|v| &v.span))
+        .chain(filter.mixin_uses(name).map(|line| &line.span))
+        .map(|span| Location::new(uri.clone(), range(span, text)))
+        .collect()
+}
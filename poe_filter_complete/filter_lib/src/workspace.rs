@@ -0,0 +1,117 @@
+use crate::imports::Imported;
+use crate::mode_parsing::{parse, Filter};
+use crate::strictness::STRICTNESS;
+use std::fs;
+use std::path::Path;
+
+/// Whether `path` is written by `filter_bin build` or `filter_bin strictness`,
+/// like `name.build.filter` or `name-3-strict.filter`.
+pub fn generated(path: &Path) -> bool {
+    let name = match path.file_name() {
+        Some(name) => name.to_string_lossy(),
+        None => return false,
+    };
+    name.ends_with(".build.filter")
+        || STRICTNESS
+            .iter()
+            .enumerate()
+            .any(|(level, s)| name.ends_with(&format!("-{}-{}.filter", level, s)))
+}
+
+/// Whether `scan` would pick up the file at `path` under `root`: a `.filter`
+/// file inside it, not generated and not in a hidden directory.
+pub fn tracked(root: &Path, path: &Path) -> bool {
+    let relative = match path.strip_prefix(root) {
+        Ok(relative) => relative,
+        Err(_) => return false,
+    };
+    let hidden = relative
+        .components()
+        .any(|c| c.as_os_str().to_string_lossy().starts_with('.'));
+    !hidden && path.extension().is_some_and(|e| e == "filter") && !generated(path)
+}
+
+/// Every `.filter` file under `root`, read and parsed. Hidden directories,
+/// generated filters and files that cannot be read are skipped.
+pub fn scan(root: &Path) -> Vec<Imported> {
+    let mut files = vec![];
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for path in entries.filter_map(|e| Some(e.ok()?.path())) {
+            let hidden = path
+                .file_name()
+                .is_some_and(|n| n.to_string_lossy().starts_with('.'));
+            if hidden {
+                continue;
+            }
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path.extension().is_some_and(|e| e == "filter") && !generated(&path) {
+                if let Ok(text) = fs::read_to_string(&path) {
+                    let filter = parse(&text);
+                    files.push(Imported { path, text, filter });
+                }
+            }
+        }
+    }
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    files
+}
+
+/// Replaces the copy of the file at `path` with `text` as the editor has it,
+/// adding the file when it is not in the workspace yet.
+pub fn update<'a>(files: &'a mut Vec<Imported>, path: &Path, text: &str) -> &'a Imported {
+    let file = Imported {
+        path: path.to_path_buf(),
+        text: text.to_string(),
+        filter: parse(text),
+    };
+    let at = match files.iter().position(|f| f.path == path) {
+        Some(at) => {
+            files[at] = file;
+            at
+        }
+        None => {
+            files.push(file);
+            files.len() - 1
+        }
+    };
+    &files[at]
+}
+
+/// Forgets the file at `path`, once it is deleted.
+pub fn remove(files: &mut Vec<Imported>, path: &Path) {
+    files.retain(|f| f.path != path);
+}
+
+/// The workspace files and imports other than the one at `path`, each once,
+/// as their path, parsed filter and text.
+pub fn others<'a>(
+    files: &'a [Imported],
+    imported: &'a [Imported],
+    path: &'a Path,
+) -> impl Iterator<Item = (&'a Path, &'a Filter, &'a str)> + 'a {
+    let files = files.iter().filter(move |f| f.path != path);
+    let known = files.clone();
+    let imports = imported.iter().enumerate().filter(move |(at, i)| {
+        let known = known
+            .clone()
+            .chain(&imported[..*at])
+            .any(|f| same_file(&f.path, &i.path));
+        !known && !same_file(&i.path, path)
+    });
+    files
+        .chain(imports.map(|(_, i)| i))
+        .map(|f| (f.path.as_path(), &f.filter, f.text.as_str()))
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
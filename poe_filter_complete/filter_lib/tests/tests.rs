@@ -1,12 +1,38 @@
 #[cfg(test)]
 mod tests {
     // use filter_lib::logos_parsing;
+    use filter_lib::code_lens;
+    use filter_lib::completion;
     use filter_lib::data_parsing;
+    use filter_lib::diagnostics;
+    use filter_lib::economy;
+    use filter_lib::hover;
+    use filter_lib::imports;
+    use filter_lib::inlay_hints;
+    use filter_lib::lookup;
+    use filter_lib::mentions;
+    use filter_lib::mixins;
     use filter_lib::mode_parsing;
+    use filter_lib::rename;
+    use filter_lib::signature_help;
+    use filter_lib::sounds;
+    use filter_lib::strictness;
+    use filter_lib::styles;
+    use filter_lib::symbols;
+    use filter_lib::tiering;
+    use filter_lib::variables;
+    use filter_lib::workspace;
     #[test]
     fn test_new_filter_block() {
         let filter_file = include_str!("../src/test_filters/small.filter");
         let x = mode_parsing::parse(filter_file);
+        assert!(!x.vec.is_empty());
+
+        let commented = "#Show # $tier->t2\n#\tBaseType \"A\"\nShow # $tier->t1\n\tClass \"B\"\n";
+        let x = mode_parsing::parse(commented);
+        assert_eq!(x.vec.len(), 1);
+        assert_eq!(x.vec[0].block, Some(mode_parsing::Token::Show));
+        assert_eq!(x.vec[0].keywords[0].token, mode_parsing::Token::Class);
 
         // for b in x.iter(){
         //     for k in b.keywords.iter(){
@@ -37,6 +63,1299 @@ mod tests {
     #[test]
     fn test_data_parsing() {
         let x = data_parsing::PoeData::new();
-        println!("{:#?}", x)
+        println!("{:#?}", x);
+
+        let mods = mode_parsing::Token::HasExplicitMod
+            .valid_values(&x)
+            .unwrap()
+            .collect::<Vec<_>>();
+        let unique = mods.iter().collect::<std::collections::HashSet<_>>();
+        assert_eq!(mods.len(), unique.len());
+        assert!(std::ptr::eq(
+            mode_parsing::Token::BaseType
+                .valid_values(&x)
+                .unwrap()
+                .next()
+                .unwrap(),
+            x.bases().items()[0].name.as_str()
+        ));
+    }
+
+    #[test]
+    fn test_argument_completion() {
+        let labels = |line: &str| {
+            let context = completion::argument_context(line, 0, line.len()).unwrap();
+            completion::argument_completion(&context, None, &data_parsing::PoeData::default())
+                .into_iter()
+                .map(|c| c.label)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(labels("\tItemLevel ")[..2], ["<", "<="]);
+        assert!(labels("\tItemLevel >= ").is_empty());
+        assert_eq!(
+            labels("\tRarity > ")[..],
+            ["Normal", "Magic", "Rare", "Unique"]
+        );
+        assert_eq!(labels("\tIdentified ")[..], ["True", "False"]);
+        assert!(labels("\tHasInfluence Elder ").contains(&"Warlord".to_string()));
+        assert_eq!(labels("\tPlayEffect Blue ")[..], ["Temp"]);
+        assert_eq!(labels("\tMinimapIcon ")[..], ["0", "1", "2"]);
+        assert!(labels("\tMinimapIcon 0 Bl").contains(&"Blue".to_string()));
+        assert!(labels("\tMinimapIcon 0 Blue ").contains(&"Diamond".to_string()));
+        assert!(labels("\tItemLevel ").contains(&"!=".to_string()));
+        assert_eq!(
+            labels("\tRarity != ")[..],
+            ["Normal", "Magic", "Rare", "Unique"]
+        );
+        assert!(completion::argument_context("\tRarit", 0, 7).is_none());
+        assert!(completion::argument_context("Show ", 0, 5).is_none());
+
+        // The line being typed is only in the editor, not on disk.
+        let text = "Show\n\tRarity ";
+        let items = completion::completion_parse(
+            lsp_types::CompletionParams {
+                text_document_position: lsp_types::TextDocumentPositionParams {
+                    text_document: lsp_types::TextDocumentIdentifier {
+                        uri: lsp_types::Url::parse("file:///nowhere/unsaved.filter").unwrap(),
+                    },
+                    position: lsp_types::Position::new(1, 8),
+                },
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+                context: None,
+            },
+            &mode_parsing::parse(text),
+            text,
+            &data_parsing::PoeData::default(),
+            false,
+        );
+        assert_eq!(
+            items.iter().map(|c| c.label.as_str()).collect::<Vec<_>>()[..2],
+            ["<", "<="]
+        );
+    }
+
+    #[test]
+    fn test_snippets() {
+        use mode_parsing::Token;
+        assert_eq!(
+            Token::SetTextColor.snippet().unwrap(),
+            "SetTextColor ${1:255} ${2:255} ${3:255} ${4:255}"
+        );
+        assert_eq!(
+            Token::PlayAlertSound.snippet().unwrap(),
+            "PlayAlertSound ${1:1} ${2:300}"
+        );
+        assert!(Token::MinimapIcon
+            .snippet()
+            .unwrap()
+            .starts_with("MinimapIcon ${1|0,1,2|} ${2|Red,Green,"));
+        assert!(Token::Show
+            .snippet()
+            .unwrap()
+            .starts_with("Show\n\tClass \"$1\"\n\tBaseType \"$2\"\n\tSetFontSize ${3:32}"));
+        assert_eq!(Token::Rarity.snippet(), None);
+        assert_eq!(Token::DisableDropSound.snippet(), None);
+
+        let item = Token::SetTextColor.create_completion_item(true);
+        assert_eq!(
+            item.insert_text_format,
+            Some(lsp_types::InsertTextFormat::Snippet)
+        );
+        let item = Token::SetTextColor.create_completion_item(false);
+        assert_eq!((item.insert_text, item.insert_text_format), (None, None));
+        assert_eq!(item.label, "SetTextColor");
+    }
+
+    #[test]
+    fn test_class_aware_base_completion() {
+        let text = "Show\n\tClass \"Rings\" \"Amulets\"\n\tDropLevel > 40\n\tItemLevel <= 60\n\tBaseType \"";
+        let filter = mode_parsing::parse(text);
+        let poe_data = data_parsing::PoeData::new();
+        let bases = filter.vec[0].possible_bases(&poe_data);
+        let count = bases.len();
+        assert!(!bases.is_empty());
+        for base in bases.iter() {
+            let class = poe_data.class_of(base).unwrap().name.clone();
+            assert!(class == "Rings" || class == "Amulets");
+            let level = base.drop_level.unwrap();
+            assert!(level > 40 && level <= 60);
+        }
+
+        let context = completion::argument_context(text, 4, 11).unwrap();
+        let items = completion::argument_completion(&context, filter.vec.first(), &poe_data);
+        assert_eq!(items.len(), count);
+        assert!(items[0].detail.as_ref().unwrap().contains("DropLevel"));
+    }
+
+    #[test]
+    fn test_signature_help() {
+        let active = |line: &str| {
+            let context = completion::argument_context(line, 0, line.len()).unwrap();
+            let help = signature_help::keyword_signature(&context).unwrap();
+            (
+                help.signatures[0].label.clone(),
+                help.active_parameter.unwrap(),
+            )
+        };
+        assert_eq!(
+            active("\tSetTextColor 255 20"),
+            ("SetTextColor <Red> <Green> <Blue> [Alpha]".to_string(), 1)
+        );
+        assert_eq!(active("\tMinimapIcon 0 Blue ").1, 2);
+        assert_eq!(active("\tPlayAlertSound 3 ").1, 1);
+        assert_eq!(active("\tPlayEffect ").1, 0);
+        assert_eq!(active("\tSocketGroup ").1, 0);
+        assert_eq!(active("\tSocketGroup >= ").1, 1);
+        let context = completion::argument_context("\tSetFontSize ", 0, 13).unwrap();
+        assert!(signature_help::keyword_signature(&context).is_none());
+
+        // The line being typed is only in the editor, not on disk.
+        let help = signature_help::signature_help(
+            lsp_types::SignatureHelpParams {
+                context: None,
+                text_document_position_params: lsp_types::TextDocumentPositionParams {
+                    text_document: lsp_types::TextDocumentIdentifier {
+                        uri: lsp_types::Url::parse("file:///nowhere/unsaved.filter").unwrap(),
+                    },
+                    position: lsp_types::Position::new(1, 18),
+                },
+                work_done_progress_params: Default::default(),
+            },
+            "Show\n\tSetTextColor 255 ",
+        )
+        .unwrap();
+        assert_eq!(help.active_parameter, Some(1));
+    }
+
+    #[test]
+    fn test_value_hover() {
+        let text = "Show\n\tClass \"Rings\"\n\tBaseType \"Two-Stone\"\n\tHasExplicitMod \"Tyrannical\"\n\tSetTextColor 255 190 0\n\tPlayAlertSound 3 300\n\tPlayAlertSoundPositional ShExalted 300\n\tPlayAlertSound ShGeneral\n";
+        let filter = mode_parsing::parse(text);
+        let poe_data = data_parsing::PoeData::new();
+        let hover = |needle: &str| {
+            let byte = text.find(needle).unwrap() + 1;
+            let (line, value) = filter.search_value(byte).unwrap();
+            hover::hover_value(line, value, &poe_data).unwrap()
+        };
+        assert!(hover("\"Rings").contains("- Rings"));
+        let bases = hover("\"Two-Stone");
+        assert!(bases.contains("Two-Stone Ring - Rings, DropLevel"));
+        assert!(hover("\"Tyrannical").contains("Domain: Item"));
+        assert!(hover("190").contains("#FFBE00FF"));
+        assert!(hover("ShExalted").contains("Exalted Orb"));
+        assert!(hover("3 300").contains("Alert Sound 3"));
+        assert!(hover("ShGeneral").contains("General"));
+        assert!(hover("300").contains("Volume"));
+    }
+
+    #[test]
+    fn test_data_dir() {
+        let dir = std::env::temp_dir().join(format!("poe_filter_data_dir_{}", std::process::id()));
+        match data_parsing::PoeData::load(Some(&dir)) {
+            (_, Some(data_parsing::DataError::Io { path, .. })) => assert_eq!(path, dir),
+            (_, other) => panic!("expected an io error, got {:?}", other),
+        }
+        std::fs::create_dir_all(&dir).unwrap();
+        match data_parsing::PoeData::from_dir(&dir) {
+            Err(data_parsing::DataError::Missing { files, .. }) => assert_eq!(files.len(), 7),
+            other => panic!("expected missing files, got {:?}", other.map(|_| ())),
+        }
+
+        std::fs::write(dir.join("mods.csv"), "domain,name\n1,Testing\n").unwrap();
+        let poe_data = data_parsing::PoeData::from_dir(&dir).unwrap();
+        assert_eq!(poe_data.mods().items().len(), 1);
+        assert_eq!(
+            poe_data.bases().items().len(),
+            data_parsing::PoeData::new().bases().items().len()
+        );
+        assert_eq!(
+            poe_data.bundled(),
+            [
+                "itemclasses.csv",
+                "baseitems.csv",
+                "prophecies.csv",
+                "enchantments.csv",
+                "uniques.csv",
+                "divination_cards.csv"
+            ]
+        );
+        assert!(poe_data.enchantments().is_sample());
+        std::fs::write(
+            dir.join("enchantments.csv"),
+            "name\nEnchantment Arc Damage\n",
+        )
+        .unwrap();
+        let poe_data = data_parsing::PoeData::from_dir(&dir).unwrap();
+        assert!(!poe_data.bundled().contains(&"enchantments.csv"));
+        assert!(!poe_data.enchantments().is_sample());
+        assert_eq!(data_parsing::PoeData::new().bundled().len(), 7);
+
+        std::fs::write(dir.join("baseitems.csv"), "name,droplevel\nBroken,high\n").unwrap();
+        match data_parsing::PoeData::from_dir(&dir) {
+            Err(data_parsing::DataError::Missing { files, .. }) => {
+                assert_eq!(files, ["itemclasses.csv"])
+            }
+            other => panic!("expected missing classes, got {:?}", other.map(|_| ())),
+        }
+        std::fs::write(dir.join("itemclasses.csv"), "id,name,category\n0,Rings,\n").unwrap();
+        let (poe_data, error) = data_parsing::PoeData::load(Some(&dir));
+        match error {
+            Some(data_parsing::DataError::Csv { file, .. }) => assert_eq!(file, "baseitems.csv"),
+            other => panic!("expected a csv error, got {:?}", other),
+        }
+        assert!(poe_data.mods().items().len() > 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_repoe_import() {
+        let classes = r#"{"Ring": {"name": "Rings"}, "Amulet": {"name": "Amulets"}}"#;
+        let bases = r#"{
+            "Metadata/Items/Rings/Ring1": {"name": "Iron Ring", "item_class": "Ring", "domain": "item",
+                "drop_level": 1, "inventory_width": 1, "inventory_height": 1, "release_state": "released",
+                "tags": ["ring", "default"], "implicits": ["AddedPhysicalDamageImplicitRing1"]},
+            "Metadata/Items/Rings/RingTest": {"name": "Test Ring", "item_class": "Ring", "domain": "item",
+                "drop_level": 1, "release_state": "unreleased"}
+        }"#;
+        let mods = r#"{
+            "AddedPhysicalDamageImplicitRing1": {"name": "", "domain": "item", "generation_type": "unique",
+                "stats": [{"id": "attack_minimum_added_physical_damage", "min": 1, "max": 1}]},
+            "IncreasedLife7": {"name": "Prime", "domain": "item", "generation_type": "prefix",
+                "type": "IncreasedLife", "required_level": 54, "spawn_weights": [{"tag": "ring", "weight": 1000}]},
+            "IncreasedLife8": {"name": "Robust", "domain": "item", "generation_type": "prefix",
+                "type": "IncreasedLife", "required_level": 64, "spawn_weights": [{"tag": "ring", "weight": 1000}]},
+            "MonsterMod": {"name": "", "domain": "monster", "generation_type": "unique"}
+        }"#;
+        let gems = r#"{"Metadata/Items/Gems/SkillGemFireball": {"base_item": {"display_name": "Fireball", "release_state": "released"}}}"#;
+        let poe_data = data_parsing::PoeData::from_repoe(classes, bases, mods, gems).unwrap();
+
+        assert_eq!(poe_data.bases().items().len(), 1);
+        let ring = &poe_data.bases().items()[0];
+        assert_eq!(
+            poe_data.class_of(ring).map(|c| c.name.as_str()),
+            Some("Rings")
+        );
+        assert_eq!(
+            (ring.width, ring.height, ring.drop_level),
+            (Some(1), Some(1), Some(1))
+        );
+        assert_eq!(ring.tags, ["ring", "default"]);
+        assert_eq!(ring.implicits.len(), 1);
+        assert_eq!(poe_data.mods().items().len(), 4);
+        let robust = poe_data.mod_by_id("IncreasedLife8").unwrap();
+        assert_eq!(robust.domain, Some(1));
+        assert_eq!(robust.affix, Some(data_parsing::Affix::Prefix));
+        assert_eq!(robust.tier, Some(1));
+        assert_eq!(poe_data.mod_by_id("IncreasedLife7").unwrap().tier, Some(2));
+        assert_eq!(poe_data.gems().items()[0].name, "Fireball");
+
+        let implicits = poe_data.implicits_of(ring);
+        assert_eq!(
+            implicits[0].stats[0].id,
+            "attack_minimum_added_physical_damage"
+        );
+        assert_eq!(poe_data.mods_for_base(ring).len(), 2);
+        assert_eq!(poe_data.bases_for_mod(robust), [ring]);
+        let class = poe_data.class_of(ring).unwrap();
+        assert_eq!(poe_data.bases_in_class(class), [ring]);
+
+        match data_parsing::PoeData::from_repoe("[", bases, mods, gems) {
+            Err(data_parsing::DataError::Json { file, .. }) => {
+                assert_eq!(file, "item_classes.json")
+            }
+            other => panic!("expected a json error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_lookup() {
+        let poe_data = data_parsing::PoeData::new();
+        let bases = poe_data.bases();
+
+        assert_eq!(bases.exact("Opal Ring")[0].name, "Opal Ring");
+        assert!(bases.exact("opal ring").is_empty());
+        let rings = bases.matching("Ring", false);
+        assert!(rings.len() > 10);
+        assert!(rings.iter().all(|b| b.name.contains("Ring")));
+        assert!(bases
+            .matching("ring", false)
+            .iter()
+            .all(|b| b.name.contains("ring")));
+        // The index finds the same bases, in the same order, as going through them all.
+        for text in ["Ring", "al R", "Ri", "e", "Orb", "zzz"] {
+            let mut scanned = bases
+                .items()
+                .iter()
+                .filter(|b| b.name.contains(text))
+                .collect::<Vec<_>>();
+            scanned.sort_by_key(|b| (b.name.to_lowercase(), b.id));
+            assert_eq!(bases.matching(text, false), scanned, "{}", text);
+        }
+
+        let opal = poe_data.base_by_name("Opal Ring").unwrap();
+        assert_eq!(poe_data.class_of(opal).unwrap().name, "Rings");
+        let robe = poe_data.base_by_name("Simple Robe").unwrap();
+        assert!(poe_data
+            .uniques_on(robe)
+            .iter()
+            .any(|u| u.name == "Skin of the Loyal"));
+        let card = poe_data.base_by_name("The Doctor").unwrap();
+        assert_eq!(poe_data.card_of(card).unwrap().name, "The Doctor");
+        assert!(poe_data.card_of(opal).is_none());
+
+        let prefix = bases.prefix("opal r");
+        assert_eq!(prefix[0].item.name, "Opal Ring");
+        assert_eq!(prefix[0].kind, lookup::MatchKind::Prefix);
+
+        let found = bases.substring("ring");
+        assert!(found
+            .iter()
+            .all(|f| f.item.name.to_lowercase().contains("ring")));
+        assert!(found.windows(2).all(|w| w[0].score >= w[1].score));
+
+        let typo = bases.fuzzy("Opla Ring");
+        assert_eq!(typo[0].item.name, "Opal Ring");
+        assert_eq!(typo[0].kind, lookup::MatchKind::Fuzzy);
+        assert_eq!(bases.search("Opal Ring")[0].kind, lookup::MatchKind::Exact);
+    }
+
+    #[test]
+    fn test_catalogs() {
+        let poe_data = data_parsing::PoeData::new();
+        assert!(poe_data
+            .gems()
+            .items()
+            .iter()
+            .any(|g| g.name == "Fireball" && !g.support));
+        assert!(!poe_data.prophecies().exact("Fated Connections").is_empty());
+
+        let text = "Show\n\tProphecy \"Fated Conections\" \"Trash to Treasure\"\n\tGemQualityType Divergent Shiny\n\tBaseType \"Opla Ring\"\n";
+        let filter = mode_parsing::parse(text);
+        let diagnostics = diagnostics::unknown_names(&filter, text, &poe_data);
+        let messages = diagnostics
+            .iter()
+            .map(|d| d.message.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(diagnostics.len(), 3, "{:?}", messages);
+        assert!(messages[0].contains("did you mean \"Fated Connections\""));
+        assert_eq!(messages[1], "Unknown quality type \"Shiny\"");
+        assert_eq!(diagnostics[1].range.start, lsp_types::Position::new(2, 26));
+        assert!(messages[2].contains("\"Opal Ring\""));
+
+        let context = completion::argument_context(text, 1, 11).unwrap();
+        let items = completion::argument_completion(&context, None, &poe_data);
+        assert!(items.iter().any(|i| i.label == "Trash to Treasure"));
+        let context = completion::argument_context("Show\n\tGemQualityType ", 1, 16).unwrap();
+        let items = completion::argument_completion(&context, None, &poe_data);
+        assert_eq!(items.len(), data_parsing::QUALITY_TYPES.len());
+
+        let (line, value) = filter.search_value(text.find("Trash").unwrap()).unwrap();
+        assert!(hover::hover_value(line, value, &poe_data)
+            .unwrap()
+            .contains("Matches 1 prophecies"));
+        let gem = "Show\n\tBaseType == \"Fireball\"\n";
+        let filter = mode_parsing::parse(gem);
+        let (line, value) = filter.search_value(gem.find("Fire").unwrap()).unwrap();
+        assert!(hover::hover_value(line, value, &poe_data)
+            .unwrap()
+            .ends_with("Skill gem"));
+
+        let full = include_str!("../src/test_filters/filter.filter");
+        let filter = mode_parsing::parse(full);
+        assert!(diagnostics::unknown_names(&filter, full, &poe_data).is_empty());
+
+        let text = "Show\n\tHasEnchantment \"Allocates Ancestral Bond\"\n\tProphecy \"Fated Connections\" \"Fated Conections\"\n";
+        let filter = mode_parsing::parse(text);
+        assert!(poe_data.enchantments().is_sample());
+        let diagnostics = diagnostics::unknown_names(&filter, text, &poe_data);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].range.start, lsp_types::Position::new(2, 30));
+        let complete = poe_data
+            .clone()
+            .with_catalogs(
+                "name\nFated Connections\n",
+                "name\nEnchantment Arc Damage\n",
+            )
+            .unwrap();
+        assert!(!complete.enchantments().is_sample());
+        let diagnostics = diagnostics::unknown_names(&filter, text, &complete);
+        assert_eq!(diagnostics.len(), 2);
+        assert!(diagnostics[0]
+            .message
+            .starts_with("Unknown enchantment \"Allocates Ancestral Bond\""));
+    }
+
+    #[test]
+    fn test_uniques() {
+        let poe_data = data_parsing::PoeData::new();
+        let robe = poe_data.base_by_name("Simple Robe").unwrap();
+        assert_eq!(poe_data.uniques_on(robe).len(), 3);
+        let doctor = poe_data.divination_cards().exact("The Doctor")[0];
+        assert_eq!(doctor.stack_size, Some(8));
+        assert!(poe_data.divination_cards().items().len() > 100);
+
+        let tier = poe_data.unique_tier(&["Tabula Rasa", "Tulfall", "Tulborn", "Not A Unique"]);
+        let bases = tier
+            .iter()
+            .map(|t| t.base.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(bases, ["Simple Robe", "Tornado Wand"]);
+        let others = tier[0]
+            .others
+            .iter()
+            .map(|u| u.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(others, ["Skin of the Loyal"]);
+        assert_eq!(tier[1].valuable.len(), 2);
+        assert!(tier[1].others.is_empty());
+
+        let text = "Show\n\tBaseType == \"Tornado Wand\" \"The Doctor\"\n";
+        let filter = mode_parsing::parse(text);
+        let (line, value) = filter.search_value(text.find("Tornado").unwrap()).unwrap();
+        let hover = hover::hover_value(line, value, &poe_data).unwrap();
+        assert!(hover.contains("Tulfall - Tornado Wand (drop disabled)"));
+        assert!(hover.contains("Matches 2 uniques (sample):"));
+        let listed = poe_data
+            .clone()
+            .with_uniques(
+                "name,base_type,drop_disabled\nTulfall,Tornado Wand,true\n",
+                "name,stack_size,reward\n",
+            )
+            .unwrap();
+        assert!(!listed.uniques().is_sample());
+        let hover = hover::hover_value(line, value, &listed).unwrap();
+        assert!(hover.contains("Matches 1 uniques:"));
+        let (line, value) = filter.search_value(text.find("Doctor").unwrap()).unwrap();
+        let hover = hover::hover_value(line, value, &poe_data).unwrap();
+        assert!(hover.contains("Divination card: 8 for Headhunter"));
+    }
+
+    #[test]
+    fn test_prices() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("src/test_filters/prices");
+        let prices = economy::Prices::from_dir(&dir).unwrap();
+
+        let exalt = prices.by_name("Exalted Orb").unwrap();
+        assert_eq!(exalt.category, economy::Category::Currency);
+        assert_eq!(exalt.confidence, economy::Confidence::High);
+        assert_eq!(
+            prices.by_name("Mirror of Kalandra").unwrap().confidence,
+            economy::Confidence::Low
+        );
+        assert_eq!(
+            prices.by_name("Shavronne's Wrappings").unwrap().chaos_value,
+            28.0
+        );
+        assert_eq!(
+            prices.by_base("The Doctor").unwrap().category,
+            economy::Category::DivinationCard
+        );
+        assert_eq!(
+            prices.by_name("Gilded Divination Scarab").unwrap().category,
+            economy::Category::Scarab
+        );
+        assert!(prices.by_base("Leather Belt").is_none());
+        assert_eq!(prices.uniques_on("Two-Stone Ring").len(), 2);
+
+        let mut poe_data = data_parsing::PoeData::new();
+        poe_data.prices = prices;
+        let text = "Show\n\tBaseType == \"The Doctor\" \"Leather Belt\"\n";
+        let filter = mode_parsing::parse(text);
+        let (line, value) = filter.search_value(text.find("Doctor").unwrap()).unwrap();
+        let hover = hover::hover_value(line, value, &poe_data).unwrap();
+        assert!(
+            hover.contains("The Doctor - Divination Card, DropLevel 1, 1450c (medium confidence)"),
+            "{}",
+            hover
+        );
+        let (line, value) = filter.search_value(text.find("Leather").unwrap()).unwrap();
+        let hover = hover::hover_value(line, value, &poe_data).unwrap();
+        assert!(
+            hover.contains("Headhunter - Leather Belt, 4600c (high confidence)"),
+            "{}",
+            hover
+        );
+
+        match economy::Prices::from_json("Currency.json", economy::Category::Currency, "{") {
+            Err(data_parsing::DataError::Json { file, .. }) => assert_eq!(file, "Currency.json"),
+            other => panic!("expected a json error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_retier() {
+        let root = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("src/test_filters");
+        let prices = economy::Prices::from_dir(&root.join("prices")).unwrap();
+        let config = tiering::TierConfig::from_file(&root.join("tiers.csv")).unwrap();
+
+        let text = "Show # $tier->currency->t12\n\tClass Currency\n\tBaseType \"Divine Orb\"\n\tSetFontSize 45\n\nShow # $type->currency $tier->t21 %H5\n\tBaseType == \"Exalted Orb\" \"Orb of Fusing\"   # hand picked\n\nShow # $tier->currency->t11\n\tBaseType == \"Mirror of Kalandra\"\n";
+        let tiering = tiering::retier(text, &config, &prices);
+        assert_eq!(
+            tiering.text,
+            "Show # $tier->currency->t12\n\tClass Currency\n\tBaseType == \"Exalted Orb\"\n\tSetFontSize 45\n\nShow # $type->currency $tier->t21 %H5\n\tBaseType == \"Divine Orb\"   # hand picked\n\nShow # $tier->currency->t11\n\tBaseType == \"Mirror of Kalandra\"\n"
+        );
+        let change = |item: &str, from: Option<&str>, to: Option<&str>| tiering::TierChange {
+            item: item.to_string(),
+            from: from.map(String::from),
+            to: to.map(String::from),
+        };
+        assert_eq!(
+            tiering.changes,
+            vec![
+                change("Divine Orb", Some("currency->t12"), Some("currency->t21")),
+                change("Exalted Orb", Some("currency->t21"), Some("currency->t12")),
+                change("Orb of Fusing", Some("currency->t21"), None),
+            ]
+        );
+        assert!(tiering.missing.contains(&"divination->t1".to_string()));
+
+        let filter_file = include_str!("../src/test_filters/filter.filter");
+        let tiering = tiering::retier(filter_file, &config, &prices);
+        assert!(tiering.missing.is_empty(), "{:?}", tiering.missing);
+        assert!(tiering
+            .text
+            .contains("$tier->t23 $type->currency\n\tClass Currency\n\tBaseType == \"Orb of Alchemy\" \"Orb of Fusing\"\n"));
+        assert_eq!(tiering.text.lines().count(), filter_file.lines().count());
+        assert!(tiering.changes.iter().any(|c| c.item == "Abandoned Wealth"
+            && c.from.as_deref() == Some("divination->t1")
+            && c.to.is_none()));
+    }
+
+    #[test]
+    fn test_strictness() {
+        let text = "Show # $type->currency $strictness>=3 hide\n\tBaseType == \"Orb of Alchemy\"\n\tSetFontSize 40   # keep\n\nShow # $strictness>=2 SetFontSize 30 $strictness>=5 drop\n    Class \"Gems\"\n    SetFontSize 40\n\nHide # $strictness<1 show $strictness>=4 PlayEffect Grey\n\tRarity Normal\n\n# footer\n";
+        let filter = mode_parsing::parse(text);
+        assert_eq!(
+            strictness::annotations(&filter.vec[1], text),
+            vec![
+                strictness::Annotation {
+                    operator: ">=".to_string(),
+                    level: 2,
+                    action: strictness::StrictAction::Set(
+                        mode_parsing::Token::SetFontSize,
+                        "30".to_string()
+                    ),
+                },
+                strictness::Annotation {
+                    operator: ">=".to_string(),
+                    level: 5,
+                    action: strictness::StrictAction::Drop,
+                },
+            ]
+        );
+
+        let variants = strictness::variants(text);
+        assert_eq!(variants.len(), strictness::STRICTNESS.len());
+        assert_eq!(variants[1], ("regular", text.to_string()));
+        assert_eq!(
+            variants[0].1,
+            text.replace("Hide # $strictness<1", "Show # $strictness<1")
+        );
+        assert_eq!(
+            variants[3].1,
+            text.replace("Show # $type", "Hide # $type")
+                .replace("    SetFontSize 40", "    SetFontSize 30")
+        );
+        assert_eq!(
+            variants[5].1,
+            "Hide # $type->currency $strictness>=3 hide\n\tBaseType == \"Orb of Alchemy\"\n\tSetFontSize 40   # keep\n\nHide # $strictness<1 show $strictness>=4 PlayEffect Grey\n\tRarity Normal\n\tPlayEffect Grey\n\n# footer\n"
+        );
+    }
+
+    #[test]
+    fn test_styles() {
+        let text = "Show # $tier->t1 $type->currency\n\tBaseType == \"Exalted Orb\"\n\tSetTextColor 255 0 0\n\tPlayAlertSound 1 300\n\nShow\n\tBaseType == \"Divine Orb\"\n\tSetTextColor 255  0 0   # same\n\tPlayAlertSound 1 300\n\nShow # %H2\n\tClass \"Gems\"\n\tSetFontSize 40\n\nHide\n\tRarity Normal\n";
+        let (style, tagged) = styles::extract(text);
+        assert_eq!(
+            style.to_string(),
+            "[currency->t1]\nSetTextColor 255 0 0\nPlayAlertSound 1 300\n\n[style-2]\nSetFontSize 40\n"
+        );
+        assert_eq!(
+            tagged,
+            text.replace(
+                "$type->currency\n",
+                "$type->currency $style->currency->t1\n"
+            )
+            .replace(
+                "Show\n\tBaseType",
+                "Show # $style->currency->t1\n\tBaseType"
+            )
+            .replace("%H2", "%H2 $style->style-2")
+        );
+        assert_eq!(
+            styles::Style::parse("default.style", &style.to_string()).unwrap(),
+            style
+        );
+        assert_eq!(
+            styles::render(&tagged, &style),
+            tagged.replace("255  0 0", "255 0 0")
+        );
+
+        let dark = styles::Style::parse(
+            "dark.style",
+            "# dark\n[currency->t1]\nSetTextColor 0 0 0\nMinimapIcon 0 Red Star\n",
+        )
+        .unwrap();
+        let rendered = styles::render(&tagged, &dark);
+        assert!(rendered.contains("\tSetTextColor 0 0 0\n\tPlayAlertSound 1 300\n\tMinimapIcon 0 Red Star\n\nShow # $style"));
+        assert!(rendered.contains("\tSetTextColor 0 0 0   # same\n"));
+        assert!(rendered.contains("\tSetFontSize 40\n"));
+
+        match styles::Style::parse("bad.style", "[x]\nBaseType \"Orb\"\n") {
+            Err(data_parsing::DataError::Syntax { line, .. }) => assert_eq!(line, 2),
+            other => panic!("expected a syntax error, got {:?}", other.map(|_| ())),
+        }
+
+        let filter_file = include_str!("../src/test_filters/filter.filter");
+        let (style, tagged) = styles::extract(filter_file);
+        assert!(style.entry("currency->t31").is_some());
+        assert_eq!(tagged.lines().count(), filter_file.lines().count());
+        assert_eq!(styles::render(&tagged, &style), tagged);
+    }
+
+    #[test]
+    fn test_variables() {
+        let text = "$gold = 255 190 0\n$bases = \"Vaal Regalia\" \"Astral Plate\"\n$more = $bases \"Glorious Plate\"\n\nShow # $tier->t1\n\tBaseType == $more\n\tSetTextColor $gold 200\n\tSetBorderColor $gold\n\tSetFontSize $size\n";
+        let filter = mode_parsing::parse(text);
+        assert_eq!(filter.variables.len(), 3);
+        assert_eq!(filter.vec.len(), 1);
+        assert_eq!(
+            variables::value_text(filter.definition("$bases").unwrap(), text),
+            "\"Vaal Regalia\" \"Astral Plate\""
+        );
+        assert_eq!(
+            variables::expand(text),
+            "\nShow # $tier->t1\n\tBaseType == \"Vaal Regalia\" \"Astral Plate\" \"Glorious Plate\"\n\tSetTextColor 255 190 0 200\n\tSetBorderColor 255 190 0\n\tSetFontSize $size\n"
+        );
+
+        let byte = text.find("$gold 200").unwrap() + 2;
+        assert_eq!(filter.variable_at(byte).unwrap().0, "$gold");
+        assert_eq!(
+            variables::hover(&filter, "$more", text, &[]),
+            "# Variable $more\n\n    \"Vaal Regalia\" \"Astral Plate\" \"Glorious Plate\""
+        );
+
+        // The editor's unsaved buffer is longer than the file on disk.
+        let saved =
+            std::env::temp_dir().join(format!("poe_filter_hover_{}.filter", std::process::id()));
+        std::fs::write(&saved, "Show\n").unwrap();
+        let at = text.find("== $more").unwrap() + 5;
+        let hovers = hover::hover_keyword(
+            lsp_types::HoverParams {
+                text_document_position_params: lsp_types::TextDocumentPositionParams {
+                    text_document: lsp_types::TextDocumentIdentifier {
+                        uri: lsp_types::Url::from_file_path(&saved).unwrap(),
+                    },
+                    position: hover::position_in_string(at, text),
+                },
+                work_done_progress_params: Default::default(),
+            },
+            &filter,
+            text,
+            &[],
+            &data_parsing::PoeData::new(),
+        );
+        std::fs::remove_file(&saved).unwrap();
+        assert_eq!(
+            hovers,
+            vec![lsp_types::MarkedString::String(variables::hover(
+                &filter,
+                "$more",
+                text,
+                &[]
+            ))]
+        );
+
+        let uri = lsp_types::Url::parse("file:///tmp/test.filter").unwrap();
+        let position = hover::position_in_string(byte, text);
+        let document = lsp_types::TextDocumentPositionParams {
+            text_document: lsp_types::TextDocumentIdentifier { uri: uri.clone() },
+            position,
+        };
+        let definition = variables::goto_definition(
+            lsp_types::GotoDefinitionParams {
+                text_document_position_params: document.clone(),
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            },
+            &filter,
+            text,
+            &[],
+        );
+        assert_eq!(
+            definition,
+            Some(lsp_types::GotoDefinitionResponse::Scalar(
+                lsp_types::Location::new(
+                    uri.clone(),
+                    lsp_types::Range::new(
+                        lsp_types::Position::new(0, 0),
+                        lsp_types::Position::new(0, 5)
+                    )
+                )
+            ))
+        );
+        let references = variables::references(
+            lsp_types::ReferenceParams {
+                text_document_position: document,
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+                context: lsp_types::ReferenceContext {
+                    include_declaration: true,
+                },
+            },
+            &filter,
+            text,
+            &[],
+        );
+        let lines = references
+            .iter()
+            .map(|l| l.range.start.line)
+            .collect::<Vec<_>>();
+        assert_eq!(lines, vec![0, 6, 7]);
+
+        let diagnostics = diagnostics::variables(&filter, text, &[]);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "Undefined variable $size");
+        let poe_data = data_parsing::PoeData::new();
+        assert!(diagnostics::unknown_names(&filter, text, &poe_data).is_empty());
+    }
+
+    #[test]
+    fn test_imports() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("src/test_filters/imports");
+        let main = dir.join("main.filter");
+        let text = std::fs::read_to_string(&main).unwrap();
+        let filter = mode_parsing::parse(&text);
+        assert_eq!(filter.imports.len(), 2);
+        assert_eq!(
+            imports::import_path(&filter.imports[0]),
+            Some("parts/colors.filter")
+        );
+
+        let bundled = imports::bundle(&main).unwrap();
+        assert_eq!(
+            variables::expand(&bundled),
+            "\nShow # $tier->t1\n\tBaseType == \"Exalted Orb\"\n\tSetTextColor 255 190 0\n\nShow # $tier->t2\n\tBaseType == \"Divine Orb\"\n\tSetBorderColor 255 190 0\n"
+        );
+
+        let imported = imports::load(&main, &filter);
+        assert_eq!(imported.len(), 2);
+        assert!(diagnostics::variables(&filter, &text, &imported).is_empty());
+        assert_eq!(diagnostics::variables(&filter, &text, &[]).len(), 1);
+        assert!(imports::problems(&main, &filter, &text, &imported).is_empty());
+
+        let links = imports::document_links(&main, &filter, &text);
+        assert_eq!(links.len(), 2);
+        assert_eq!(links[0].range.start, lsp_types::Position::new(0, 8));
+        assert!(links[1]
+            .target
+            .path()
+            .ends_with("imports/parts/currency.filter"));
+
+        let uri = lsp_types::Url::from_file_path(&main).unwrap();
+        let at = |needle: &str| lsp_types::GotoDefinitionParams {
+            text_document_position_params: lsp_types::TextDocumentPositionParams {
+                text_document: lsp_types::TextDocumentIdentifier { uri: uri.clone() },
+                position: hover::position_in_string(text.find(needle).unwrap() + 1, &text),
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        match variables::goto_definition(at("$gold"), &filter, &text, &imported) {
+            Some(lsp_types::GotoDefinitionResponse::Scalar(location)) => {
+                assert!(location.uri.path().ends_with("parts/colors.filter"));
+                assert_eq!(location.range.end, lsp_types::Position::new(0, 5));
+            }
+            other => panic!("expected a definition, got {:?}", other),
+        }
+        match variables::goto_definition(at("parts/currency"), &filter, &text, &imported) {
+            Some(lsp_types::GotoDefinitionResponse::Scalar(location)) => {
+                assert!(location.uri.path().ends_with("parts/currency.filter"))
+            }
+            other => panic!("expected the imported file, got {:?}", other),
+        }
+
+        let cycle = dir.join("cycle_a.filter");
+        match imports::bundle(&cycle) {
+            Err(imports::ImportError::Cycle { chain }) => assert_eq!(chain.len(), 3),
+            other => panic!("expected an import cycle, got {:?}", other),
+        }
+        let text = std::fs::read_to_string(&cycle).unwrap();
+        let filter = mode_parsing::parse(&text);
+        let imported = imports::load(&cycle, &filter);
+        let problems = imports::problems(&cycle, &filter, &text, &imported);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].message.starts_with("import cycle"));
+        let missing = "Import \"nowhere.filter\"\n";
+        let problems = imports::problems(&main, &mode_parsing::parse(missing), missing, &[]);
+        assert!(problems[0].message.starts_with("could not read"));
+
+        let diamond = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("src/test_filters/diamond/main.filter");
+        let bundled = imports::bundle(&diamond).unwrap();
+        assert_eq!(bundled.matches("Mirror of Kalandra").count(), 1);
+        assert!(bundled.find("Mirror").unwrap() < bundled.find("Divine").unwrap());
+        assert!(bundled.contains("Exalted Orb"));
+    }
+
+    #[test]
+    fn test_mixins() {
+        let text = "Mixin $loud\n\tSetFontSize 45\n\tPlayAlertSound 1 300\n\tMinimapIcon 0 Red Star\n\nMixin $boxed\n\t$loud\n\tSetBorderColor 255 0 0\n\nShow # $tier->t1\n\tBaseType \"Mirror of Kalandra\"\n\t$boxed\n\tSetFontSize 40\n\tSetTextColor $red\n\t$quiet\n";
+        let filter = mode_parsing::parse(text);
+        assert_eq!(filter.mixins.len(), 2);
+        assert_eq!(filter.vec.len(), 1);
+        assert!(filter.variables.is_empty());
+        let inside = text.find("255 0 0").unwrap();
+        let block = filter.search_block(inside).unwrap();
+        assert_eq!(block.block, Some(mode_parsing::Token::Mixin));
+        assert_eq!(block.bspan.start, text.find("Mixin $boxed").unwrap());
+        let (line, _) = filter.search_value(inside).unwrap();
+        assert_eq!(line.token, mode_parsing::Token::SetBorderColor);
+        assert_eq!(
+            mixins::lines(filter.mixin("$boxed").unwrap(), text),
+            vec!["$loud", "SetBorderColor 255 0 0"]
+        );
+        assert_eq!(
+            mixins::expand(text),
+            "Show # $tier->t1\n\tBaseType \"Mirror of Kalandra\"\n\tPlayAlertSound 1 300\n\tMinimapIcon 0 Red Star\n\tSetBorderColor 255 0 0\n\tSetFontSize 40\n\tSetTextColor $red\n\t$quiet\n"
+        );
+        let byte = text.find("$boxed\n\tSetFontSize").unwrap() + 1;
+        assert_eq!(filter.variable_at(byte).unwrap().0, "$boxed");
+        assert!(variables::hover(&filter, "$boxed", text, &[]).starts_with("# Mixin $boxed"));
+        let messages = diagnostics::variables(&filter, text, &[])
+            .into_iter()
+            .map(|d| d.message)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            vec!["Undefined mixin $quiet", "Undefined variable $red"]
+        );
+
+        let text = "Show # $type->currency $tier->t1\n\tBaseType \"Exalted Orb\"\n\tSetFontSize 45\n\tSetTextColor 255 0 0\n\nShow # $type->currency $tier->t2\n\tBaseType \"Divine Orb\"\n\tSetFontSize  45\n\tSetTextColor 255 0 0\n\nShow\n\tSetFontSize 30\n";
+        let filter = mode_parsing::parse(text);
+        let uri = lsp_types::Url::parse("file:///tmp/test.filter").unwrap();
+        let params = lsp_types::CodeActionParams {
+            text_document: lsp_types::TextDocumentIdentifier { uri: uri.clone() },
+            range: lsp_types::Range::new(
+                lsp_types::Position::new(2, 3),
+                lsp_types::Position::new(2, 3),
+            ),
+            context: Default::default(),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let actions = mixins::extract(&params, &filter, text);
+        let edit = match &actions[..] {
+            [lsp_types::CodeActionOrCommand::CodeAction(action)] => {
+                assert_eq!(
+                    action.title,
+                    "Extract actions into mixin $currency (2 blocks)"
+                );
+                action.edit.clone().unwrap()
+            }
+            other => panic!("expected one code action, got {:?}", other),
+        };
+        let edits = edit.changes.unwrap().remove(&uri).unwrap();
+        let edits = edits
+            .into_iter()
+            .map(|e| {
+                let start = hover::byte_pos_in_string(
+                    e.range.start.line as usize,
+                    e.range.start.character as usize,
+                    text.to_string(),
+                );
+                let end = hover::byte_pos_in_string(
+                    e.range.end.line as usize,
+                    e.range.end.character as usize,
+                    text.to_string(),
+                );
+                (start..end, e.new_text)
+            })
+            .collect();
+        let extracted = mode_parsing::apply_edits(text, edits);
+        assert_eq!(
+            extracted,
+            "Mixin $currency\n\tSetFontSize 45\n\tSetTextColor 255 0 0\n\nShow # $type->currency $tier->t1\n\tBaseType \"Exalted Orb\"\n\t$currency\n\nShow # $type->currency $tier->t2\n\tBaseType \"Divine Orb\"\n\t$currency\n\nShow\n\tSetFontSize 30\n"
+        );
+        assert_eq!(mixins::expand(&extracted), text.replace("  45", " 45"));
+    }
+
+    #[test]
+    fn test_mentions() {
+        let text = "Show # $tier->t1\n\tBaseType == \"Exalted Orb\" \"Divine Orb\"\n\tSetTextColor 255 0 0 200\n\nShow\n\tClass \"Exalted Orb\"\n\tSetBorderColor 255 0 0\n\nShow\n\tBaseType \"Exalted Orb\"\n\tSetTextColor 255 0 1\n\nShow\n\tBaseType \"Orb\"\n\nShow\n\tBaseType == \"Orb\"\n";
+        let filter = mode_parsing::parse(text);
+        let byte = text.find("Exalted").unwrap();
+        let (mention, span) = mentions::mention_at(&filter, text, byte).unwrap();
+        assert_eq!(
+            mention,
+            mentions::Mention::Name(mode_parsing::Token::BaseType, String::from("Exalted Orb"))
+        );
+        assert_eq!(&text[span], "\"Exalted Orb\"");
+        let lines = |spans: Vec<std::ops::Range<usize>>| {
+            spans
+                .iter()
+                .map(|s| hover::position_in_string(s.start, text).line)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            lines(mentions::mentions(&filter, text, &mention)),
+            vec![1, 9, 13]
+        );
+
+        let (color, span) =
+            mentions::mention_at(&filter, text, text.find("0 0 200").unwrap()).unwrap();
+        assert_eq!(color, mentions::Mention::Color([255, 0, 0]));
+        assert_eq!(&text[span], "255 0 0");
+        assert_eq!(lines(mentions::mentions(&filter, text, &color)), vec![2, 6]);
+
+        let other = "Show\n\tBaseType \"Exalted Orb\"\n";
+        let others = vec![imports::Imported {
+            path: std::path::PathBuf::from("/tmp/other.filter"),
+            text: other.to_string(),
+            filter: mode_parsing::parse(other),
+        }];
+        let uri = lsp_types::Url::parse("file:///tmp/test.filter").unwrap();
+        let position = lsp_types::TextDocumentPositionParams {
+            text_document: lsp_types::TextDocumentIdentifier { uri },
+            position: hover::position_in_string(byte, text),
+        };
+        let references = mentions::references(
+            lsp_types::ReferenceParams {
+                text_document_position: position.clone(),
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+                context: lsp_types::ReferenceContext {
+                    include_declaration: true,
+                },
+            },
+            &filter,
+            text,
+            workspace::others(&others, &[], std::path::Path::new("/tmp/test.filter")),
+        );
+        assert_eq!(references.len(), 4);
+        assert!(references[3].uri.path().ends_with("other.filter"));
+        let highlights = mentions::highlights(
+            lsp_types::DocumentHighlightParams {
+                text_document_position_params: position,
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            },
+            &filter,
+            text,
+        );
+        assert_eq!(highlights.len(), 3);
+    }
+
+    #[test]
+    fn test_rename() {
+        let text = "Show # $type->expl->rare $tier->t1\n\tBaseType == \"Exalted Orb\"\n\nShow # $type->expl->rare\n\tBaseType Exalted \"Exalted Orb\"\n";
+        let filter = mode_parsing::parse(text);
+        let tags = filter.vec[0].tags(text);
+        assert_eq!(tags[0].0, "type");
+        assert_eq!(&text[tags[0].2.clone()], "expl->rare");
+        assert_eq!(filter.vec[0].tag(text, "tier"), Some("t1"));
+
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("src/test_filters/imports");
+        let files = workspace::scan(&dir);
+        assert_eq!(files.len(), 5);
+        let main = dir.join("main.filter");
+        let others = workspace::others(&files, &[], &main);
+        assert_eq!(others.count(), 4);
+        assert!(workspace::generated(std::path::Path::new(
+            "main.build.filter"
+        )));
+        assert!(workspace::generated(std::path::Path::new(
+            "main-3-strict.filter"
+        )));
+        assert!(!workspace::generated(std::path::Path::new(
+            "main-strict.filter"
+        )));
+        assert!(files.iter().all(|f| workspace::tracked(&dir, &f.path)));
+        assert!(!workspace::tracked(&dir, &dir.join("main.build.filter")));
+        assert!(!workspace::tracked(&dir, &dir.join(".git/main.filter")));
+        assert!(!workspace::tracked(
+            &dir,
+            std::path::Path::new("/tmp/main.filter")
+        ));
+
+        let uri = lsp_types::Url::parse("file:///tmp/test.filter").unwrap();
+        let at = |needle: &str| lsp_types::TextDocumentPositionParams {
+            text_document: lsp_types::TextDocumentIdentifier { uri: uri.clone() },
+            position: hover::position_in_string(text.find(needle).unwrap() + 2, text),
+        };
+        match rename::prepare_rename(at("Exalted Orb"), &filter, text) {
+            Some(lsp_types::PrepareRenameResponse::RangeWithPlaceholder {
+                placeholder, ..
+            }) => {
+                assert_eq!(placeholder, "Exalted Orb")
+            }
+            other => panic!("expected a placeholder, got {:?}", other),
+        }
+        let renamed = |position, new_name: &str, others: &[filter_lib::imports::Imported]| {
+            let edit = rename::rename(
+                lsp_types::RenameParams {
+                    text_document_position: position,
+                    new_name: new_name.to_string(),
+                    work_done_progress_params: Default::default(),
+                },
+                &filter,
+                text,
+                workspace::others(others, &[], std::path::Path::new("/tmp/test.filter")),
+            )
+            .ok()??;
+            let edits = edit.changes?.remove(&uri)?;
+            let edits = edits
+                .into_iter()
+                .map(|e| {
+                    let start = hover::byte_pos_in_string(
+                        e.range.start.line as usize,
+                        e.range.start.character as usize,
+                        text.to_string(),
+                    );
+                    let end = hover::byte_pos_in_string(
+                        e.range.end.line as usize,
+                        e.range.end.character as usize,
+                        text.to_string(),
+                    );
+                    (start..end, e.new_text)
+                })
+                .collect();
+            Some(mode_parsing::apply_edits(text, edits))
+        };
+        assert_eq!(
+            renamed(at("Exalted Orb"), "Exalted Orb of Patch", &[]).unwrap(),
+            "Show # $type->expl->rare $tier->t1\n\tBaseType == \"Exalted Orb of Patch\"\n\nShow # $type->expl->rare\n\tBaseType Exalted \"Exalted Orb of Patch\"\n"
+        );
+        assert_eq!(
+            renamed(at("expl->rare"), "expl->magic", &[]).unwrap(),
+            "Show # $type->expl->magic $tier->t1\n\tBaseType == \"Exalted Orb\"\n\nShow # $type->expl->magic\n\tBaseType Exalted \"Exalted Orb\"\n"
+        );
+        let invalid = rename::rename(
+            lsp_types::RenameParams {
+                text_document_position: at("expl->rare"),
+                new_name: String::from("expl rare"),
+                work_done_progress_params: Default::default(),
+            },
+            &filter,
+            text,
+            std::iter::empty(),
+        );
+        assert!(invalid.unwrap_err().contains("expl rare"));
+
+        let other = "Show\n\tBaseType \"Exalted Orb\"\n\tClass \"Exalted Orb\"\n";
+        let others = vec![filter_lib::imports::Imported {
+            path: std::path::PathBuf::from("/tmp/other.filter"),
+            text: other.to_string(),
+            filter: mode_parsing::parse(other),
+        }];
+        let edit = rename::rename(
+            lsp_types::RenameParams {
+                text_document_position: at("Exalted Orb"),
+                new_name: String::from("Divine Orb"),
+                work_done_progress_params: Default::default(),
+            },
+            &filter,
+            text,
+            workspace::others(&others, &[], std::path::Path::new("/tmp/test.filter")),
+        )
+        .unwrap()
+        .unwrap();
+        let changes = edit.changes.unwrap();
+        let other_uri = lsp_types::Url::from_file_path("/tmp/other.filter").unwrap();
+        assert_eq!(changes[&other_uri].len(), 1);
+    }
+
+    #[test]
+    fn test_symbols() {
+        let text = include_str!("../src/test_filters/filter.filter");
+        let file = filter_lib::imports::Imported {
+            path: std::path::PathBuf::from("/tmp/filter.filter"),
+            text: text.to_string(),
+            filter: mode_parsing::parse(text),
+        };
+        let symbols = symbols::symbols(&file);
+        let banner = symbols
+            .iter()
+            .find(|s| s.name == "[[0200]] 6 LINKS")
+            .unwrap();
+        assert_eq!(banner.kind, lsp_types::SymbolKind::Module);
+        assert_eq!(banner.location.range.start.line, 274);
+        // The table of contents lists the banners without rules around them.
+        assert_eq!(
+            symbols
+                .iter()
+                .filter(|s| s.name.starts_with("[[0200]]"))
+                .count(),
+            1
+        );
+
+        let mut index = symbols::SymbolIndex::new(&[file]);
+        let divine = index.search("divine orb");
+        assert!(divine
+            .iter()
+            .any(|s| s.name == "Divine Orb" && s.kind == lsp_types::SymbolKind::Constant));
+        let tiers = index.search("t1 currency");
+        assert!(!tiers.is_empty());
+        assert!(tiers.iter().all(|s| s.name.contains("t1")
+            || s.container_name
+                .as_deref()
+                .unwrap_or_default()
+                .contains("t1")));
+
+        let mut files = vec![];
+        let other = filter_lib::workspace::update(
+            &mut files,
+            std::path::Path::new("/tmp/other.filter"),
+            "Show # $type->maps $tier->t1\n\tBaseType == \"Divine Vessel\"\n",
+        );
+        index.update(other);
+        assert_eq!(index.search("divine vessel").len(), 1);
+        index.remove(std::path::Path::new("/tmp/filter.filter"));
+        assert_eq!(index.search("t1").len(), 2);
+    }
+
+    #[test]
+    fn test_sounds() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("src/test_filters/sounds");
+        let path = dir.join("sounds.filter");
+        let text = "Show\n\tCustomAlertSound \"alert.mp3\" 300\n\tCustomAlertSound \"missing.wav\"\n\tCustomAlertSound \"notes.txt\"\n\tCustomAlertSound \"None\"\n\tCustomAlertSound \"none\" 100\n";
+        let filter = mode_parsing::parse(text);
+
+        let links = sounds::document_links(&path, &filter, text);
+        assert_eq!(links.len(), 3);
+        assert!(links[0].target.path().ends_with("sounds/alert.mp3"));
+        assert_eq!(links[0].range.start, lsp_types::Position::new(1, 19));
+        assert_eq!(links[0].range.end, lsp_types::Position::new(1, 28));
+
+        let problems = sounds::problems(&path, &filter, text);
+        assert_eq!(problems.len(), 2);
+        assert_eq!(
+            problems[0].severity,
+            Some(lsp_types::DiagnosticSeverity::Warning)
+        );
+        assert!(problems[0].message.contains("missing.wav"));
+        assert_eq!(
+            problems[1].severity,
+            Some(lsp_types::DiagnosticSeverity::Error)
+        );
+
+        let labels = |typed| {
+            sounds::completion(&path, typed)
+                .into_iter()
+                .map(|item| item.label)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(labels(""), vec!["alert.mp3", "more"]);
+        assert_eq!(labels("more/ch"), vec!["chime.wav"]);
+        assert!(labels("nowhere/").is_empty());
+
+        let context = completion::argument_context(text, 1, 20).unwrap();
+        assert_eq!(context.keyword, mode_parsing::Token::CustomAlertSound);
+        assert!(context.in_quotes);
+    }
+
+    #[test]
+    fn test_code_lens() {
+        let poe_data = data_parsing::PoeData::new();
+        let text = "Show\n\tClass \"Rings\"\n\nShow\n\tBaseType == \"Coral Ring\"\n\nShow\n\tClass \"Amulets\"\n\tContinue\n\nShow\n\tClass \"Amulets\"\n\tRarity Unique\n\nShow\n\tBaseType == \"No Such Base\"\n";
+        let filter = mode_parsing::parse(text);
+        let uri = lsp_types::Url::parse("file:///tmp/test.filter").unwrap();
+        let lenses = code_lens::code_lenses(&uri, &filter, text, &poe_data);
+        let titles = lenses
+            .iter()
+            .map(|l| l.command.as_ref().unwrap().title.as_str())
+            .collect::<Vec<_>>();
+        let rings = poe_data
+            .bases()
+            .items()
+            .iter()
+            .filter(|b| poe_data.class_of(b).is_some_and(|c| c.name == "Rings"))
+            .count();
+        assert_eq!(
+            titles[0],
+            format!("{} bases · 1 class · drop level 1–80 · reachable", rings)
+        );
+        assert_eq!(
+            titles[1],
+            "1 base · 1 class · drop level 4–4 · unreachable, caught on line 1"
+        );
+        assert!(titles[2].ends_with("reachable") && !titles[2].contains("unreachable"));
+        assert!(titles[3].ends_with(" · reachable"));
+        assert_eq!(titles[4], "matches no known bases");
+        assert_eq!(lenses[1].range.start.line, 3);
+
+        let message = code_lens::bases_message(&filter, text, 3, &poe_data).unwrap();
+        assert_eq!(message, "1 base: Coral Ring");
+        let all = "Show\n\tDropLevel >= 1\n";
+        let count = code_lens::matching_bases(&mode_parsing::parse(all).vec[0], &poe_data).len();
+        let message =
+            code_lens::bases_message(&mode_parsing::parse(all), all, 0, &poe_data).unwrap();
+        assert!(message.starts_with(&format!("{} bases: ", count)));
+        assert!(message.ends_with(&format!(", and {} more", count - 20)));
+        for condition in ["> 80", "<= 10", "= 45", "!= 100", "== 101"] {
+            let block = format!("Show\n\tItemLevel {}\n", condition);
+            let filter = mode_parsing::parse(&block);
+            let line = &filter.vec[0].keywords[0];
+            for (min, max) in [(1, 100), (45, 45), (81, 100), (100, 100)] {
+                assert_eq!(
+                    line.matches_range(min, max),
+                    (min..=max).any(|l| line.matches_number(l)),
+                    "ItemLevel {} over {}–{}",
+                    condition,
+                    min,
+                    max
+                );
+            }
+        }
+
+        let text = "$rings = \"Coral Ring\" \"Iron Ring\"\n\nMixin $rare\n\tBaseType == \"Coral Ring\"\n\nShow\n\tBaseType == $rings\n\nShow\n\t$rare\n\nShow\n\tBaseType == $amulets\n";
+        let filter = mode_parsing::parse(text);
+        let lenses = code_lens::code_lenses(&uri, &filter, text, &poe_data);
+        let titles = lenses
+            .iter()
+            .map(|l| l.command.as_ref().unwrap().title.as_str())
+            .collect::<Vec<_>>();
+        assert!(titles[0].starts_with("2 bases · 1 class"));
+        assert!(titles[1].starts_with("1 base · 1 class") && titles[1].contains("unreachable"));
+        assert_eq!(titles[2], "bases unknown, $amulets is not defined here");
+        let message = code_lens::bases_message(&filter, text, 5, &poe_data).unwrap();
+        assert_eq!(message, "2 bases: Coral Ring, Iron Ring");
+    }
+
+    #[test]
+    fn test_inlay_hints() {
+        let poe_data = data_parsing::PoeData::new();
+        let text = "Show\n\tBaseType \"Ring\" \"Coral Ring\"\n\tBaseType == \"Coral Ring\"\n\tPlayAlertSound 3 300\n\tMinimapIcon 0 Blue Diamond\n\tSetTextColor 255 0 0\n\tSetBorderColor 0 0 0 200\n";
+        let filter = mode_parsing::parse(text);
+        let everything = lsp_types::Range::new(
+            lsp_types::Position::new(0, 0),
+            lsp_types::Position::new(8, 0),
+        );
+        let hints = inlay_hints::inlay_hints(everything, &filter, text, &poe_data)
+            .into_iter()
+            .map(|h| (h.position.line, h.position.character, h.label))
+            .collect::<Vec<_>>();
+        let rings = poe_data.bases().matching("Ring", false).len();
+        assert_eq!(
+            hints,
+            vec![
+                (1, 16, format!("⟶ {} bases", rings)),
+                (1, 29, String::from("⟶ 1 base")),
+                (3, 17, String::from("Alert Sound 3")),
+                (4, 27, String::from("large blue diamond")),
+                (5, 21, String::from("#FF0000")),
+                (6, 25, String::from("#000000C8")),
+            ]
+        );
+
+        let visible = lsp_types::Range::new(
+            lsp_types::Position::new(3, 0),
+            lsp_types::Position::new(4, 0),
+        );
+        let hints = inlay_hints::inlay_hints(visible, &filter, text, &poe_data);
+        assert_eq!(hints.len(), 1);
+        let json = serde_json::to_value(&hints[0]).unwrap();
+        assert_eq!(json["paddingLeft"], serde_json::Value::Bool(true));
     }
 }